@@ -0,0 +1,160 @@
+#![forbid(unsafe_code)]
+#![warn(clippy::all)]
+
+use crate::config::SessionApprovalsConfig;
+use crate::hook_io::{session_file_stem, HookInput};
+use crate::matcher::resolve_path;
+use anyhow::{Context, Result};
+use log::warn;
+use nix::fcntl::{Flock, FlockArg};
+use nix::unistd::geteuid;
+use std::fs::{DirBuilder, OpenOptions};
+use std::io::Write;
+use std::os::unix::fs::{DirBuilderExt, MetadataExt, OpenOptionsExt};
+use std::path::{Path, PathBuf};
+
+// ========== SESSION APPROVALS ==========
+// Purpose: "Yes, just this file" - let a denied file through for the rest of a session
+// Location: ~/.local/state/claude-hook-approvals/<session_id>.txt (or configured directory), one path per line
+
+fn approvals_file(dir: &Path, session_id: &str) -> PathBuf {
+    dir.join(format!("{}.txt", session_file_stem(session_id)))
+}
+
+/// Record an approval for `path` in `session_id`; relative paths are made absolute
+pub fn approve(config: &SessionApprovalsConfig, session_id: &str, path: &Path) -> Result<PathBuf> {
+    let path = std::path::absolute(path)
+        .with_context(|| format!("Failed to resolve path: {}", path.display()))?;
+
+    DirBuilder::new()
+        .recursive(true)
+        .mode(0o700)
+        .create(&config.dir)
+        .with_context(|| format!("Failed to create approvals directory: {}", config.dir.display()))?;
+    check_private(&config.dir)?;
+    let file_path = approvals_file(&config.dir, session_id);
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .mode(0o600)
+        .open(&file_path)
+        .with_context(|| format!("Failed to open approvals file: {}", file_path.display()))?;
+    check_private(&file_path)?;
+
+    let mut flock = Flock::lock(file, FlockArg::LockExclusive).map_err(|(_, e)| e)?;
+    writeln!(flock, "{}", path.display())?;
+    Ok(path)
+}
+
+/// The input's target file, if the session has approved it
+pub fn approved_path(config: &SessionApprovalsConfig, input: &HookInput) -> Option<PathBuf> {
+    if !config.enabled {
+        return None;
+    }
+    let target = resolve_path(&input.cwd_path(), &input.extract_path("file_path")?);
+
+    let file_path = approvals_file(&config.dir, &input.session_id);
+    let contents = match std::fs::read_to_string(&file_path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return None,
+        Err(e) => {
+            warn!("Failed to read session approvals {}: {}", file_path.display(), e);
+            return None;
+        }
+    };
+    if let Err(e) = check_private(&config.dir).and_then(|()| check_private(&file_path)) {
+        warn!("Ignoring session approvals {}: {:#}", file_path.display(), e);
+        return None;
+    }
+
+    contents
+        .lines()
+        .any(|line| Path::new(line) == target)
+        .then_some(target)
+}
+
+/// Approvals are only trusted from a file and directory that the current user owns and
+/// nobody else can write, so another account can't plant them
+fn check_private(path: &Path) -> Result<()> {
+    let metadata = std::fs::metadata(path).with_context(|| format!("Failed to inspect {}", path.display()))?;
+    if metadata.uid() != geteuid().as_raw() {
+        anyhow::bail!("{} is not owned by the current user", path.display());
+    }
+    if metadata.mode() & 0o022 != 0 {
+        anyhow::bail!(
+            "{} is writable by group or others (mode {:o})",
+            path.display(),
+            metadata.mode() & 0o777
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn approvals_config() -> SessionApprovalsConfig {
+        SessionApprovalsConfig {
+            enabled: true,
+            dir: std::env::temp_dir().join(format!("hook-approvals-test-{}", rand::random::<u64>())),
+        }
+    }
+
+    fn read_input(session_id: &str, file_path: &str) -> HookInput {
+        HookInput {
+            session_id: session_id.to_string(),
+            transcript_path: "/tmp/test".to_string(),
+            cwd: "/home/user/project".to_string(),
+            hook_event_name: "PreToolUse".to_string(),
+            tool_name: "Read".to_string(),
+            tool_input: serde_json::json!({"file_path": file_path}),
+            extra: serde_json::Map::new(),
+            input_root: None,
+            path_style: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_approval_is_scoped_to_session_and_path() -> Result<()> {
+        let config = approvals_config();
+        approve(&config, "session-1", Path::new("/home/user/project/.env"))?;
+
+        let expected = Some(PathBuf::from("/home/user/project/.env"));
+        assert_eq!(approved_path(&config, &read_input("session-1", "/home/user/project/.env")), expected);
+        // Relative paths resolve against the input's cwd
+        assert_eq!(approved_path(&config, &read_input("session-1", ".env")), expected);
+        assert_eq!(approved_path(&config, &read_input("session-1", "/home/user/project/.env.prod")), None);
+        assert_eq!(approved_path(&config, &read_input("session-2", "/home/user/project/.env")), None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_disabled_approvals_are_ignored() -> Result<()> {
+        let mut config = approvals_config();
+        approve(&config, "session-1", Path::new("/etc/hosts"))?;
+        config.enabled = false;
+        assert_eq!(approved_path(&config, &read_input("session-1", "/etc/hosts")), None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_approvals_others_can_write_are_ignored() -> Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+        let config = approvals_config();
+        approve(&config, "session-1", Path::new("/etc/hosts"))?;
+        let mode = |path: &Path| std::fs::metadata(path).map(|m| m.mode() & 0o777);
+        assert_eq!(mode(&config.dir)?, 0o700);
+        assert_eq!(mode(&approvals_file(&config.dir, "session-1"))?, 0o600);
+
+        let file_path = approvals_file(&config.dir, "session-1");
+        std::fs::set_permissions(&file_path, std::fs::Permissions::from_mode(0o622))?;
+        assert_eq!(approved_path(&config, &read_input("session-1", "/etc/hosts")), None);
+
+        std::fs::set_permissions(&file_path, std::fs::Permissions::from_mode(0o600))?;
+        std::fs::set_permissions(&config.dir, std::fs::Permissions::from_mode(0o777))?;
+        assert_eq!(approved_path(&config, &read_input("session-1", "/etc/hosts")), None);
+        assert!(approve(&config, "session-1", Path::new("/etc/passwd")).is_err());
+        Ok(())
+    }
+}
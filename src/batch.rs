@@ -0,0 +1,367 @@
+#![forbid(unsafe_code)]
+#![warn(clippy::all)]
+
+use crate::config::Config;
+use crate::hook_io::HookInput;
+use crate::logging::{compute_review_flags, flag_audit_matches, flag_match_timeouts};
+use crate::{apply_risk_threshold, evaluate, Decision};
+use anyhow::{Context, Result};
+use log::warn;
+use std::collections::BTreeMap;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+
+/// Decision distribution over a batch of inputs
+#[derive(Debug, Default)]
+pub struct BatchSummary {
+    pub total: usize,
+    pub invalid: usize,
+    pub by_decision: BTreeMap<String, usize>,
+    pub by_source: BTreeMap<String, usize>,
+    /// Latency of LLM calls actually made (cache hits excluded)
+    pub llm_latencies_ms: Vec<u64>,
+    /// Matches of rules from `enforce = false` sections, by rule id
+    pub audit_matches: BTreeMap<String, usize>,
+}
+
+impl BatchSummary {
+    fn record(&mut self, decision: &Decision) {
+        self.total += 1;
+        *self.by_decision.entry(decision.decision.to_string()).or_default() += 1;
+        *self.by_source.entry(decision.source.to_string()).or_default() += 1;
+        for rule_id in &decision.audit_matches {
+            *self.audit_matches.entry(rule_id.clone()).or_default() += 1;
+        }
+        if let Some(llm_metadata) = &decision.llm_metadata
+            && !llm_metadata.cached
+            && let Some(ms) = llm_metadata.processing_time_ms
+        {
+            self.llm_latencies_ms.push(ms);
+        }
+    }
+
+    pub fn passthrough_rate(&self) -> f64 {
+        if self.total == 0 {
+            return 0.0;
+        }
+        *self.by_decision.get("passthrough").unwrap_or(&0) as f64 / self.total as f64
+    }
+
+    /// Nearest-rank percentile of LLM latency
+    pub fn latency_percentile(&self, percentile: f64) -> Option<u64> {
+        let mut sorted = self.llm_latencies_ms.clone();
+        sorted.sort_unstable();
+        let rank = ((percentile / 100.0) * sorted.len() as f64).ceil() as usize;
+        sorted.get(rank.max(1) - 1).copied()
+    }
+
+    pub fn write_report(&self, out: &mut impl Write) -> io::Result<()> {
+        let counts = |map: &BTreeMap<String, usize>| {
+            map.iter()
+                .map(|(key, count)| format!("{} {}", key, count))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+
+        writeln!(out, "Batch summary")?;
+        writeln!(out, "  Inputs:       {}", self.total)?;
+        writeln!(out, "  Invalid:      {}", self.invalid)?;
+        writeln!(out, "  Decisions:    {}", counts(&self.by_decision))?;
+        writeln!(out, "  Sources:      {}", counts(&self.by_source))?;
+        writeln!(out, "  Passthrough:  {:.1}%", self.passthrough_rate() * 100.0)?;
+        if !self.audit_matches.is_empty() {
+            writeln!(out, "  Audit-only:   {}", counts(&self.audit_matches))?;
+        }
+        match (
+            self.latency_percentile(50.0),
+            self.latency_percentile(90.0),
+            self.latency_percentile(99.0),
+        ) {
+            (Some(p50), Some(p90), Some(p99)) => writeln!(
+                out,
+                "  LLM latency:  p50 {}ms, p90 {}ms, p99 {}ms ({} calls)",
+                p50,
+                p90,
+                p99,
+                self.llm_latencies_ms.len()
+            ),
+            _ => writeln!(out, "  LLM latency:  no LLM calls"),
+        }
+    }
+}
+
+/// Rule option and value a suggested stub matches on; `None` selects the tool alone
+type SuggestionField = Option<(&'static str, String)>;
+
+/// Passthrough inputs grouped by tool and observed value, turned into rule stubs to review
+#[derive(Debug, Default)]
+pub struct RuleSuggestions {
+    counts: BTreeMap<(String, SuggestionField), usize>,
+}
+
+impl RuleSuggestions {
+    fn record(&mut self, input: &HookInput) {
+        let key = (input.tool_name.clone(), suggestion_field(input));
+        *self.counts.entry(key).or_default() += 1;
+    }
+
+    /// Write one allow stub per input seen at least `min_count` times, most frequent first
+    /// Returns the number of stubs written
+    pub fn write_toml(&self, out: &mut impl Write, min_count: usize) -> io::Result<usize> {
+        let mut stubs: Vec<_> =
+            self.counts.iter().filter(|(_, count)| **count >= min_count).collect();
+        stubs.sort_by(|(key_a, count_a), (key_b, count_b)| {
+            count_b.cmp(count_a).then_with(|| key_a.cmp(key_b))
+        });
+
+        writeln!(
+            out,
+            "# Rule stubs suggested from passthrough inputs seen at least {} time(s)",
+            min_count
+        )?;
+        writeln!(out, "# Review each one before adopting it; move any that should be blocked to deny")?;
+        writeln!(out)?;
+        writeln!(out, "[suggested]")?;
+        writeln!(out, "description = \"Rules suggested from passthrough traffic\"")?;
+
+        let mut per_tool: BTreeMap<&str, usize> = BTreeMap::new();
+        for ((tool, field), count) in &stubs {
+            let n = per_tool.entry(tool.as_str()).or_default();
+            *n += 1;
+            writeln!(out)?;
+            match field {
+                Some(_) => writeln!(out, "# Seen {} time(s)", count)?,
+                None => writeln!(out, "# Seen {} time(s); matches every {} call", count, tool)?,
+            }
+            writeln!(out, "[[suggested.allow]]")?;
+            writeln!(out, "id = \"suggested-{}-{}\"", kebab_case(tool), n)?;
+            writeln!(out, "tool = {}", toml::Value::String(tool.clone()))?;
+            if let Some((option, value)) = field {
+                writeln!(out, "{} = {}", option, toml::Value::String(value.clone()))?;
+            }
+        }
+        Ok(stubs.len())
+    }
+}
+
+/// The rule option and value a stub for this input would match on
+/// Regex options get the observed value escaped and anchored, so the stub matches only it
+fn suggestion_field(input: &HookInput) -> SuggestionField {
+    let (option, field) = match input.tool_name.as_str() {
+        "Bash" => ("command_regex", "command"),
+        "Read" | "Write" | "Edit" | "MultiEdit" | "Glob" => ("file_path_regex", "file_path"),
+        "Task" => ("subagent_type", "subagent_type"),
+        _ => return None,
+    };
+    let value = input.extract_field(field)?;
+    if option.ends_with("_regex") {
+        Some((option, format!("^{}$", regex::escape(&value))))
+    } else {
+        Some((option, value))
+    }
+}
+
+fn kebab_case(tool: &str) -> String {
+    tool.to_lowercase()
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+/// Evaluate every HookInput in a JSONL file, printing one decision per line
+/// Nothing is logged, so a corpus can be replayed without touching the audit logs
+pub async fn run_batch(
+    config_path: &Path,
+    profile: Option<&str>,
+    input_path: &Path,
+    summary: bool,
+    suggest_rules: Option<(&Path, usize)>,
+) -> Result<()> {
+    let compiled = Config::load_from_file_with_profile(config_path, profile)
+        .context("Failed to load configuration")?;
+    let file = std::fs::File::open(input_path)
+        .with_context(|| format!("Failed to open batch input: {}", input_path.display()))?;
+
+    let mut stats = BatchSummary::default();
+    let mut suggestions = RuleSuggestions::default();
+    let mut stdout = io::stdout();
+
+    for (idx, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.context("Failed to read batch input")?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let input = match serde_json::from_str::<HookInput>(&line) {
+            Ok(input) => input
+                .with_input_root(compiled.matching.input_root.clone())
+                .with_path_style(compiled.matching.path_style),
+            Err(e) => {
+                warn!("Skipping line {}: invalid hook input: {}", idx + 1, e);
+                stats.invalid += 1;
+                continue;
+            }
+        };
+
+        let decision = evaluate(&compiled, config_path, &input, false, None).await;
+        let mut review_flags = compute_review_flags(
+            decision.decision,
+            decision.source,
+            &input.tool_name,
+            &input.tool_input,
+            &decision.reasoning,
+            &decision.rule_metadata,
+            &decision.llm_metadata,
+        );
+        flag_match_timeouts(&mut review_flags, &decision.match_timeouts);
+        flag_audit_matches(&mut review_flags, &decision.audit_matches);
+        let (decision, _) = apply_risk_threshold(
+            decision,
+            review_flags,
+            compiled.logging.auto_deny_risk_level.as_deref(),
+        );
+
+        let result = serde_json::json!({
+            "line": idx + 1,
+            "tool_name": input.tool_name,
+            "decision": decision.decision,
+            "source": decision.source,
+            "reasoning": decision.reasoning,
+        });
+        writeln!(stdout, "{}", result).context("Failed to write to stdout")?;
+        stats.record(&decision);
+        if decision.decision == "passthrough" {
+            suggestions.record(&input);
+        }
+    }
+
+    if let Some((path, min_count)) = suggest_rules {
+        let mut file = std::fs::File::create(path)
+            .with_context(|| format!("Failed to create rule suggestions: {}", path.display()))?;
+        let written = suggestions
+            .write_toml(&mut file, min_count)
+            .with_context(|| format!("Failed to write rule suggestions: {}", path.display()))?;
+        eprintln!("Wrote {} suggested rule(s) to {}", written, path.display());
+    }
+
+    if summary {
+        stats.write_report(&mut io::stderr()).context("Failed to write summary")?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logging::LlmMetadata;
+
+    fn decision(decision: &'static str, source: &'static str, llm_ms: Option<u64>) -> Decision {
+        Decision {
+            output: None,
+            decision,
+            source,
+            reasoning: String::new(),
+            rule_metadata: None,
+            llm_metadata: llm_ms.map(|ms| LlmMetadata {
+                assessment: "ALLOW".to_string(),
+                reasoning: String::new(),
+                confidence: None,
+                processing_time_ms: Some(ms),
+                model: "test".to_string(),
+                provider: None,
+                concerns: Vec::new(),
+                cached: false,
+                model_verdicts: Vec::new(),
+                attempts: Vec::new(),
+                prompt: None,
+            }),
+            match_timeouts: Vec::new(),
+            audit_matches: Vec::new(),
+            passthrough_reason: None,
+        }
+    }
+
+    #[test]
+    fn test_summary_counts() {
+        let mut stats = BatchSummary::default();
+        stats.record(&decision("deny", "rule", None));
+        stats.record(&decision("allow", "rule", None));
+        stats.record(&decision("allow", "rule", None));
+        stats.record(&decision("allow", "llm", Some(300)));
+        stats.record(&decision("passthrough", "passthrough", Some(100)));
+        stats.invalid = 1;
+
+        assert_eq!(stats.total, 5);
+        assert_eq!(stats.by_decision["allow"], 3);
+        assert_eq!(stats.by_decision["deny"], 1);
+        assert_eq!(stats.by_source["rule"], 3);
+        assert_eq!(stats.by_source["llm"], 1);
+        assert!((stats.passthrough_rate() - 0.2).abs() < f64::EPSILON);
+        assert_eq!(stats.latency_percentile(50.0), Some(100));
+        assert_eq!(stats.latency_percentile(99.0), Some(300));
+
+        let mut report = Vec::new();
+        stats.write_report(&mut report).unwrap();
+        let report = String::from_utf8(report).unwrap();
+        assert!(report.contains("Decisions:    allow 3, deny 1, passthrough 1"));
+        assert!(report.contains("Passthrough:  20.0%"));
+        assert!(report.contains("p50 100ms, p90 300ms, p99 300ms (2 calls)"));
+    }
+
+    fn passthrough_input(tool: &str, tool_input: serde_json::Value) -> HookInput {
+        serde_json::from_value(serde_json::json!({
+            "session_id": "test",
+            "transcript_path": "/tmp/test",
+            "cwd": "/home/user",
+            "hook_event_name": "PreToolUse",
+            "tool_name": tool,
+            "tool_input": tool_input,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_rule_suggestions_from_passthroughs() -> Result<()> {
+        let mut suggestions = RuleSuggestions::default();
+        for _ in 0..3 {
+            let input = passthrough_input("Bash", serde_json::json!({"command": "cargo test"}));
+            suggestions.record(&input);
+        }
+        for _ in 0..2 {
+            let input = passthrough_input("mcp__docs__search", serde_json::json!({"q": "x"}));
+            suggestions.record(&input);
+        }
+        let input = passthrough_input("Read", serde_json::json!({"file_path": "/tmp/a.txt"}));
+        suggestions.record(&input);
+
+        let mut out = Vec::new();
+        assert_eq!(suggestions.write_toml(&mut out, 2)?, 2);
+        let stubs = String::from_utf8(out)?;
+        assert!(stubs.contains("id = \"suggested-bash-1\""), "{}", stubs);
+        assert!(stubs.contains(r#"command_regex = "^cargo test$""#), "{}", stubs);
+        assert!(stubs.contains("matches every mcp__docs__search call"), "{}", stubs);
+        assert!(!stubs.contains("a.txt"), "{}", stubs);
+        // Most frequent first
+        assert!(stubs.find("suggested-bash-1") < stubs.find("suggested-mcp-docs-search-1"));
+
+        // The stubs are a loadable config whose rules match what was observed
+        let path =
+            std::env::temp_dir().join(format!("hook-suggestions-{}.toml", std::process::id()));
+        std::fs::write(&path, &stubs)?;
+        let compiled = Config::load_from_file_with_profile(&path, None);
+        std::fs::remove_file(&path)?;
+        let compiled = compiled?;
+        let ids: Vec<&str> = compiled.allow_rules.iter().map(|rule| rule.id.as_str()).collect();
+        assert_eq!(ids, ["suggested-bash-1", "suggested-mcp-docs-search-1"]);
+        let input = passthrough_input("Bash", serde_json::json!({"command": "cargo test"}));
+        let regex = compiled.allow_rules[0].command_regex.as_ref().unwrap();
+        assert!(regex.is_match(&input.extract_field("command").unwrap()));
+        assert!(!regex.is_match("cargo test && rm -rf /"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_latency_percentile_without_llm_calls() {
+        assert_eq!(BatchSummary::default().latency_percentile(50.0), None);
+    }
+}
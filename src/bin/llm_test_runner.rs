@@ -6,10 +6,11 @@ use clap::Parser;
 use csv::ReaderBuilder;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::fs::File;
+use std::fs::{File, OpenOptions};
 use std::io::Write;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
+use std::time::Instant;
 
 #[derive(Debug, Parser)]
 #[clap(author, version, about = "LLM fallback test runner")]
@@ -33,6 +34,10 @@ struct Opts {
     /// Sample N random test cases (useful for quick testing)
     #[clap(short, long)]
     sample: Option<usize>,
+
+    /// Append this run's summary metrics as a row to this CSV, to track drift across runs
+    #[clap(long)]
+    trend_csv: Option<PathBuf>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -40,6 +45,7 @@ struct TestCase {
     id: String,
     tool_name: String,
     expected_class: String,
+    #[allow(dead_code)] // Documents the case in the CSV; not used in reports
     description: String,
     tool_input_key: String,
     tool_input_value: String,
@@ -86,6 +92,28 @@ struct TestResult {
     llm_reasoning: String,
     correct: bool,
     error: Option<String>,
+    /// Wall time of the hook run, process startup included
+    latency_ms: u64,
+}
+
+/// One run's summary, appended to the trend CSV
+#[derive(Debug, Serialize)]
+struct TrendRow {
+    timestamp: String,
+    model: String,
+    config_hash: String,
+    cases: usize,
+    errors: usize,
+    accuracy: f64,
+    allow_f1: f64,
+    query_f1: f64,
+    mean_latency_ms: f64,
+}
+
+/// Model and config hash the hook reports for a config, "unknown" where it doesn't say
+struct ConfigIdentity {
+    model: String,
+    config_hash: String,
 }
 
 #[derive(Debug, Default)]
@@ -137,6 +165,9 @@ fn main() -> Result<()> {
     println!("Config:        {}", opts.config.display());
     println!("Report:        {}", opts.output.display());
     println!("Results CSV:   {}", opts.results_csv.display());
+    if let Some(trend_csv) = &opts.trend_csv {
+        println!("Trend CSV:     {}", trend_csv.display());
+    }
     println!();
 
     // Load test cases
@@ -172,6 +203,12 @@ fn main() -> Result<()> {
     println!("📝 Generating reports...");
     write_markdown_report(&opts.output, &results, accuracy, &per_class_metrics)?;
     write_csv_results(&opts.results_csv, &results)?;
+    if let Some(trend_csv) = &opts.trend_csv {
+        let identity = config_identity(&opts.config);
+        let row = trend_row(&results, accuracy, &per_class_metrics, identity);
+        append_trend_row(trend_csv, &row)?;
+        println!("   Appended run to {}", trend_csv.display());
+    }
     println!();
 
     // Print summary
@@ -238,6 +275,7 @@ fn run_single_test(test_case: &TestCase, config_path: &PathBuf) -> TestResult {
 
     let json_str = serde_json::to_string(&hook_input).unwrap();
 
+    let started = Instant::now();
     // Execute hook via subprocess (using release build for speed)
     let output = Command::new("cargo")
         .args(["run", "--quiet", "--release", "--bin", "claude-code-permissions-hook", "--", "run", "--config"])
@@ -253,6 +291,7 @@ fn run_single_test(test_case: &TestCase, config_path: &PathBuf) -> TestResult {
             }
             child.wait_with_output()
         });
+    let latency_ms = started.elapsed().as_millis() as u64;
 
     let expected_class = Classification::from_str(&test_case.expected_class)
         .unwrap_or(Classification::Query);
@@ -273,6 +312,7 @@ fn run_single_test(test_case: &TestCase, config_path: &PathBuf) -> TestResult {
                     llm_reasoning: "".to_string(),
                     correct: false,
                     error: Some("No output in test mode (unexpected)".to_string()),
+                    latency_ms,
                 }
             } else {
                 // Parse JSON output
@@ -297,6 +337,7 @@ fn run_single_test(test_case: &TestCase, config_path: &PathBuf) -> TestResult {
                             llm_reasoning: reasoning.to_string(),
                             correct: expected_class == llm_class,
                             error: None,
+                            latency_ms,
                         }
                     }
                     Err(e) => TestResult {
@@ -309,6 +350,7 @@ fn run_single_test(test_case: &TestCase, config_path: &PathBuf) -> TestResult {
                         llm_reasoning: "".to_string(),
                         correct: false,
                         error: Some(format!("Failed to parse JSON: {}", e)),
+                        latency_ms,
                     },
                 }
             }
@@ -325,6 +367,7 @@ fn run_single_test(test_case: &TestCase, config_path: &PathBuf) -> TestResult {
                 llm_reasoning: "".to_string(),
                 correct: false,
                 error: Some(format!("Process failed: {}", stderr)),
+                latency_ms,
             }
         }
         Err(e) => TestResult {
@@ -337,6 +380,7 @@ fn run_single_test(test_case: &TestCase, config_path: &PathBuf) -> TestResult {
             llm_reasoning: "".to_string(),
             correct: false,
             error: Some(format!("Failed to execute: {}", e)),
+            latency_ms,
         },
     }
 }
@@ -478,6 +522,64 @@ fn write_csv_results(path: &PathBuf, results: &[TestResult]) -> Result<()> {
     Ok(())
 }
 
+/// Ask the hook's `validate` command which model and config hash this config resolves to
+fn config_identity(config_path: &Path) -> ConfigIdentity {
+    let output = Command::new("cargo")
+        .args(["run", "--quiet", "--release", "--bin", "claude-code-permissions-hook", "--", "validate", "--config"])
+        .arg(config_path)
+        .output();
+    let stderr = output.map(|o| String::from_utf8_lossy(&o.stderr).into_owned()).unwrap_or_default();
+    let reported = |label: &str| {
+        stderr
+            .lines()
+            .find_map(|line| line.split_once(label).map(|(_, value)| value.trim().to_string()))
+            .unwrap_or_else(|| "unknown".to_string())
+    };
+    ConfigIdentity {
+        model: reported("Model: "),
+        config_hash: reported("Config hash: "),
+    }
+}
+
+fn trend_row(
+    results: &[TestResult],
+    accuracy: f64,
+    per_class_metrics: &HashMap<Classification, ClassMetrics>,
+    identity: ConfigIdentity,
+) -> TrendRow {
+    let answered: Vec<u64> = results.iter().filter(|r| r.error.is_none()).map(|r| r.latency_ms).collect();
+    let mean_latency_ms = if answered.is_empty() {
+        0.0
+    } else {
+        answered.iter().sum::<u64>() as f64 / answered.len() as f64
+    };
+    TrendRow {
+        timestamp: chrono::Utc::now().to_rfc3339(),
+        model: identity.model,
+        config_hash: identity.config_hash,
+        cases: results.len(),
+        errors: results.iter().filter(|r| r.error.is_some()).count(),
+        accuracy,
+        allow_f1: per_class_metrics[&Classification::Allow].f1_score(),
+        query_f1: per_class_metrics[&Classification::Query].f1_score(),
+        mean_latency_ms,
+    }
+}
+
+/// Append one row, writing the header first when the file is new or empty
+fn append_trend_row(path: &Path, row: &TrendRow) -> Result<()> {
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("Failed to open trend CSV: {}", path.display()))?;
+    let needs_header = file.metadata()?.len() == 0;
+    let mut wtr = csv::WriterBuilder::new().has_headers(needs_header).from_writer(file);
+    wtr.serialize(row)?;
+    wtr.flush()?;
+    Ok(())
+}
+
 fn print_summary(
     results: &[TestResult],
     accuracy: f64,
@@ -506,3 +608,54 @@ fn print_summary(
     }
     println!("━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━");
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(expected: &str, got: &str, latency_ms: u64) -> TestResult {
+        TestResult {
+            id: "1".to_string(),
+            tool_name: "Bash".to_string(),
+            tool_input_key: "command".to_string(),
+            tool_input_value: "ls".to_string(),
+            expected_class: expected.to_string(),
+            llm_class: got.to_string(),
+            llm_reasoning: String::new(),
+            correct: expected == got,
+            error: None,
+            latency_ms,
+        }
+    }
+
+    #[test]
+    fn test_trend_row_appended() -> Result<()> {
+        let path = std::env::temp_dir().join(format!("llm-trend-{}.csv", rand::random::<u64>()));
+        let results = vec![result("ALLOW", "ALLOW", 100), result("QUERY", "ALLOW", 300)];
+        let (accuracy, per_class) = calculate_metrics(&results);
+        let identity = || ConfigIdentity {
+            model: "test-model".to_string(),
+            config_hash: "0123456789abcdef".to_string(),
+        };
+
+        append_trend_row(&path, &trend_row(&results, accuracy, &per_class, identity()))?;
+        append_trend_row(&path, &trend_row(&results, accuracy, &per_class, identity()))?;
+
+        let mut reader = ReaderBuilder::new().has_headers(true).from_path(&path)?;
+        assert_eq!(
+            reader.headers()?.iter().collect::<Vec<_>>(),
+            [
+                "timestamp", "model", "config_hash", "cases", "errors", "accuracy", "allow_f1", "query_f1",
+                "mean_latency_ms"
+            ]
+        );
+        let rows: Vec<csv::StringRecord> = reader.records().collect::<Result<_, _>>()?;
+        // The header is written once, then one row per run
+        assert_eq!(rows.len(), 2);
+        let fields: Vec<&str> = rows[1].iter().skip(1).collect();
+        assert_eq!(fields, ["test-model", "0123456789abcdef", "2", "0", "0.5", "0.6666666666666666", "0.0", "200.0"]);
+        assert!(chrono::DateTime::parse_from_rfc3339(&rows[1][0]).is_ok());
+        std::fs::remove_file(&path)?;
+        Ok(())
+    }
+}
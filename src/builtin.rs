@@ -0,0 +1,188 @@
+#![forbid(unsafe_code)]
+#![warn(clippy::all)]
+
+use crate::config::{BuiltinConfig, SectionConfig};
+use anyhow::{Context, Result};
+use std::path::PathBuf;
+
+/// Prefix of the section names built-in packs compile into, so their rules
+/// show up as e.g. `builtin-debug-tools` in logs and traces
+const SECTION_PREFIX: &str = "builtin-";
+
+/// Debuggers and tracers can read and modify the memory of other processes,
+/// including secrets held by them
+const DEBUG_TOOLS: &str = r#"
+description = "Built-in: debuggers and syscall/library tracers"
+priority = 10
+
+[[deny]]
+id = "builtin-deny-debug-tools"
+description = "Attaching a debugger or tracer can expose secrets in other processes"
+tool = "Bash"
+command_regex = '(^\s*|[;&|(`]\s*)(sudo\s+)?(strace|ltrace|gdb|lldb)(\s|$)'
+split_statements = true
+"#;
+
+/// Decoding a payload and running it hides the real command from every other rule
+const OBFUSCATION: &str = r#"
+description = "Built-in: encoded payloads decoded and executed"
+priority = 10
+
+[[deny]]
+id = "builtin-deny-obfuscated-payloads"
+description = "Decoding and executing an encoded payload hides what the command really does"
+tool = "Bash"
+detect_obfuscation = true
+"#;
+
+/// Editing the hook's own config or binary would let the agent switch its guardrails off,
+/// and recording a session approval would let it lift a deny
+/// `{paths}` is replaced with a regex matching exactly the protected paths
+const SELF_PROTECTION: &str = r#"
+description = "Built-in: the hook's own config files and executable"
+priority = 0
+
+[[deny]]
+id = "builtin-deny-self-modification"
+description = "The hook's config files and binary can't be changed from inside the session"
+tool_regex = "^(Write|Edit|MultiEdit)$"
+file_path_regex = {paths}
+
+[[deny]]
+id = "builtin-deny-self-modification-bash"
+description = "The hook's config files and binary can't be changed from inside the session"
+tool = "Bash"
+file_path_regex = {paths}
+match_path_args = true
+
+[[deny]]
+id = "builtin-deny-self-approval"
+description = "Session approvals have to come from the user, not from inside the session"
+tool = "Bash"
+command_regex = '(^|[\s/;&|(`])claude-code-permissions-hook(\s+\S+)*\s+approve(\s|$)'
+"#;
+
+/// Whether a compiled section came from a built-in pack rather than the user's config
+pub fn is_builtin_section(section_name: &str) -> bool {
+    section_name.starts_with(SECTION_PREFIX)
+}
+
+/// Built-in packs that are enabled, as (section name, section) pairs
+pub fn enabled_sections(config: &BuiltinConfig) -> Result<Vec<(String, SectionConfig)>> {
+    let packs = [
+        ("debug-tools", config.debug_tools.enabled, DEBUG_TOOLS),
+        ("obfuscation", config.obfuscation.enabled, OBFUSCATION),
+    ];
+
+    packs
+        .into_iter()
+        .filter(|(_, enabled, _)| *enabled)
+        .map(|(name, _, toml_str)| {
+            let section: SectionConfig = toml::from_str(toml_str)
+                .with_context(|| format!("Built-in pack '{}' is invalid", name))?;
+            Ok((format!("{}{}", SECTION_PREFIX, name), section))
+        })
+        .collect()
+}
+
+/// The self-protection pack for these paths, which are only known once the config is loaded
+/// Each path also matches in its canonical form and, under $HOME, as `~/...`
+/// A `protected_dirs` entry matches the directory and everything under it
+pub fn self_protection_section(
+    protected: &[PathBuf],
+    protected_dirs: &[PathBuf],
+) -> Result<(String, SectionConfig)> {
+    let alternatives: Vec<String> = spellings(protected)
+        .iter()
+        .map(|s| regex::escape(s))
+        .chain(spellings(protected_dirs).iter().map(|s| format!("{}(/.*)?", regex::escape(s))))
+        .collect();
+    let paths = toml::Value::String(format!("^({})$", alternatives.join("|")));
+    let section: SectionConfig = toml::from_str(&SELF_PROTECTION.replace("{paths}", &paths.to_string()))
+        .context("Built-in pack 'self-protection' is invalid")?;
+    Ok((format!("{}self-protection", SECTION_PREFIX), section))
+}
+
+/// Every way a rule might see these paths spelled: as given, canonical, and `~/...` under $HOME
+fn spellings(paths: &[PathBuf]) -> Vec<String> {
+    let home = std::env::var_os("HOME").map(PathBuf::from);
+    let mut spellings: Vec<String> = Vec::new();
+    for path in paths {
+        let canonical = std::fs::canonicalize(path).ok();
+        for path in std::iter::once(path).chain(canonical.as_ref()) {
+            spellings.push(path.display().to_string());
+            if let Some(home) = &home
+                && let Ok(relative) = path.strip_prefix(home)
+            {
+                spellings.push(format!("~/{}", relative.display()));
+            }
+        }
+    }
+    spellings.sort();
+    spellings.dedup();
+    spellings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use regex::Regex;
+
+    #[test]
+    fn test_debug_tools_pattern() {
+        let sections = enabled_sections(&BuiltinConfig::default()).unwrap();
+        assert_eq!(sections.len(), 2);
+        let (name, section) = &sections[0];
+        assert!(is_builtin_section(name));
+
+        let regex = Regex::new(section.deny[0].command_regex.as_deref().unwrap()).unwrap();
+        for command in ["strace -p 1234", "sudo gdb -p 1", "ls | ltrace cat", "lldb"] {
+            assert!(regex.is_match(command), "{}", command);
+        }
+        for command in ["cargo build", "grep strace notes.txt", "echo gdbserver"] {
+            assert!(!regex.is_match(command), "{}", command);
+        }
+    }
+
+    #[test]
+    fn test_self_protection_paths() {
+        let home = PathBuf::from(std::env::var("HOME").unwrap());
+        let protected = [home.join(".claude/hook (1).toml"), PathBuf::from("/usr/bin/hook")];
+        let (name, section) = self_protection_section(&protected, &[PathBuf::from("/srv/approvals")]).unwrap();
+        assert_eq!(name, "builtin-self-protection");
+        assert_eq!(section.priority, 0);
+
+        let regex = Regex::new(section.deny[0].file_path_regex.as_deref().unwrap()).unwrap();
+        let config = home.join(".claude/hook (1).toml").display().to_string();
+        let protected = [config.as_str(), "~/.claude/hook (1).toml", "/usr/bin/hook", "/srv/approvals/s1.txt"];
+        for path in protected {
+            assert!(regex.is_match(path), "{}", path);
+        }
+        for path in [
+            "/usr/bin/hook2",
+            "/tmp/.claude/hook (1).toml",
+            "~/.claude/hook 1.toml",
+            "/srv/approvals2",
+        ] {
+            assert!(!regex.is_match(path), "{}", path);
+        }
+        assert!(section.deny[1].match_path_args);
+
+        let approve = Regex::new(section.deny[2].command_regex.as_deref().unwrap()).unwrap();
+        for command in [
+            "claude-code-permissions-hook approve --session s1 --path .env",
+            "./target/release/claude-code-permissions-hook --config hook.toml approve --path .env",
+        ] {
+            assert!(approve.is_match(command), "{}", command);
+        }
+        assert!(!approve.is_match("claude-code-permissions-hook validate --config approve.toml"));
+    }
+
+    #[test]
+    fn test_packs_can_be_disabled() {
+        let config: BuiltinConfig = toml::from_str("[obfuscation]\nenabled = false\n").unwrap();
+        let sections = enabled_sections(&config).unwrap();
+        let names: Vec<&str> = sections.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, ["builtin-debug-tools"]);
+    }
+}
@@ -0,0 +1,282 @@
+#![forbid(unsafe_code)]
+#![warn(clippy::all)]
+
+use crate::config::{stable_hash, CacheConfig, QueryDecision};
+use crate::hook_io::HookInput;
+use crate::logging::LlmMetadata;
+use crate::matcher::resolve_path;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use log::{debug, warn};
+use nix::fcntl::{Flock, FlockArg};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::OpenOptions;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+// ========== DECISION CACHE ==========
+// Purpose: Skip repeat LLM calls for identical inputs
+// Location: /tmp/claude-hook-decision-cache.json (or configured path)
+
+/// Tools whose decision can depend on the current state of `file_path`
+const FILE_TOOLS: &[&str] = &["Read", "Write", "Edit", "MultiEdit"];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CacheEntry {
+    pub decision: String,
+    pub reasoning: String,
+    pub llm_metadata: LlmMetadata,
+    pub created_at: DateTime<Utc>,
+    /// `config_hash` of the config that made the decision; entries from any other config are stale
+    #[serde(default)]
+    pub config_hash: String,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct CacheFile {
+    entries: HashMap<String, CacheEntry>,
+}
+
+/// Cache key for an input: tool name, tool_input and cwd, and for file tools a fingerprint
+/// of the target file (resolved against the cwd) so edits to it invalidate earlier decisions
+/// A QUERY is cached as the decision it became, so how QUERY maps to a decision is part of the key
+pub fn cache_key(input: &HookInput, test_mode: bool, query_decision: QueryDecision) -> String {
+    let tool_input = input.tool_input.to_string();
+    let query_mode = if test_mode { "test".to_string() } else { format!("{:?}", query_decision) };
+    let mut parts = vec![
+        input.tool_name.as_str(),
+        tool_input.as_str(),
+        input.cwd.as_str(),
+        query_mode.as_str(),
+    ];
+
+    let fingerprint = FILE_TOOLS
+        .contains(&input.tool_name.as_str())
+        .then(|| input.extract_path("file_path"))
+        .flatten()
+        .map(|file_path| file_fingerprint(&resolve_path(&input.cwd_path(), &file_path)));
+    parts.extend(fingerprint.as_deref());
+
+    stable_hash(&parts)
+}
+
+/// Modification time and size, or "missing" when the file can't be inspected
+fn file_fingerprint(path: &Path) -> String {
+    match std::fs::metadata(path) {
+        Ok(metadata) => {
+            let modified_ns = metadata
+                .modified()
+                .ok()
+                .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                .map_or(0, |d| d.as_nanos());
+            format!("{}:{}", modified_ns, metadata.len())
+        }
+        Err(_) => "missing".to_string(),
+    }
+}
+
+/// Return the cached decision for `key` if present, not expired and made under `config_hash`
+/// Entries made under any other config are purged on the way
+pub fn lookup(config: &CacheConfig, config_hash: &str, key: &str) -> Option<CacheEntry> {
+    let result = with_locked_cache(&config.file, |cache| {
+        purge_stale(cache, config_hash);
+        let entry = cache.entries.get(key)?;
+        let age = Utc::now().signed_duration_since(entry.created_at);
+        if age.num_seconds() >= config.ttl_secs as i64 {
+            debug!("Cache entry {} expired", key);
+            return None;
+        }
+        Some(entry.clone())
+    });
+
+    match result {
+        Ok(entry) => entry,
+        Err(e) => {
+            warn!("Failed to read decision cache: {}", e);
+            None
+        }
+    }
+}
+
+/// Store a decision, dropping any expired entries and those from another config
+pub fn store(config: &CacheConfig, key: &str, entry: CacheEntry) {
+    let result = with_locked_cache(&config.file, |cache| {
+        purge_stale(cache, &entry.config_hash);
+        let now = Utc::now();
+        cache
+            .entries
+            .retain(|_, e| now.signed_duration_since(e.created_at).num_seconds() < config.ttl_secs as i64);
+        cache.entries.insert(key.to_string(), entry);
+    });
+
+    if let Err(e) = result {
+        warn!("Failed to write decision cache: {}", e);
+    }
+}
+
+/// Remove every cached decision, returning how many there were
+pub fn clear(config: &CacheConfig) -> Result<usize> {
+    with_locked_cache(&config.file, |cache| {
+        let count = cache.entries.len();
+        cache.entries.clear();
+        count
+    })
+}
+
+/// Drop entries tagged with a config hash other than the current one
+fn purge_stale(cache: &mut CacheFile, config_hash: &str) {
+    let before = cache.entries.len();
+    cache.entries.retain(|_, entry| entry.config_hash == config_hash);
+    let purged = before - cache.entries.len();
+    if purged > 0 {
+        debug!("Purged {} cached decision(s) made under a different config", purged);
+    }
+}
+
+/// Read-modify-write the cache file under an exclusive lock
+fn with_locked_cache<T>(path: &Path, f: impl FnOnce(&mut CacheFile) -> T) -> Result<T> {
+    let file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .read(true)
+        .write(true)
+        .open(path)
+        .with_context(|| format!("Failed to open cache file: {}", path.display()))?;
+
+    let mut flock = Flock::lock(file, FlockArg::LockExclusive).map_err(|(_, e)| e)?;
+
+    let mut contents = String::new();
+    flock.read_to_string(&mut contents)?;
+    // A corrupt or empty cache is simply rebuilt
+    let mut cache: CacheFile = serde_json::from_str(&contents).unwrap_or_default();
+
+    let result = f(&mut cache);
+
+    flock.seek(SeekFrom::Start(0))?;
+    flock.set_len(0)?;
+    flock.write_all(serde_json::to_string(&cache)?.as_bytes())?;
+
+    flock.unlock().map_err(|(_, e)| e)?;
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logging::create_llm_metadata;
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("hook-cache-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir.join(format!("{}-{}", name, rand::random::<u64>()))
+    }
+
+    fn edit_input(file_path: &Path) -> HookInput {
+        HookInput {
+            session_id: "test".to_string(),
+            transcript_path: "/tmp/test".to_string(),
+            cwd: "/home/user/project".to_string(),
+            hook_event_name: "PreToolUse".to_string(),
+            tool_name: "Edit".to_string(),
+            tool_input: serde_json::json!({
+                "file_path": file_path.display().to_string(),
+                "old_string": "a",
+                "new_string": "b"
+            }),
+            extra: serde_json::Map::new(),
+            input_root: None,
+            path_style: Default::default(),
+        }
+    }
+
+    fn allow_entry() -> CacheEntry {
+        CacheEntry {
+            decision: "allow".to_string(),
+            reasoning: "LLM: safe edit".to_string(),
+            llm_metadata: create_llm_metadata("ALLOW", "safe edit", "test-model", Some(5), None, Vec::new()),
+            created_at: Utc::now(),
+            config_hash: "config-a".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_modifying_target_file_invalidates_cache() {
+        let target = temp_path("target.rs");
+        std::fs::write(&target, "fn main() {}").unwrap();
+        let config = CacheConfig {
+            enabled: true,
+            file: temp_path("cache.json"),
+            ttl_secs: 300,
+        };
+
+        let input = edit_input(&target);
+        let key = cache_key(&input, false, QueryDecision::Passthrough);
+        store(&config, &key, allow_entry());
+        assert!(lookup(&config, "config-a", &cache_key(&input, false, QueryDecision::Passthrough)).is_some());
+
+        std::fs::write(&target, "fn main() { std::process::exit(1) }").unwrap();
+        let new_key = cache_key(&input, false, QueryDecision::Passthrough);
+        assert_ne!(key, new_key);
+        assert!(lookup(&config, "config-a", &new_key).is_none());
+    }
+
+    #[test]
+    fn test_relative_target_is_fingerprinted_against_input_cwd() {
+        let target = temp_path("target.rs");
+        std::fs::write(&target, "fn main() {}").unwrap();
+        let mut input = edit_input(Path::new(target.file_name().unwrap()));
+        input.cwd = target.parent().unwrap().display().to_string();
+        let key = |input: &HookInput| cache_key(input, false, QueryDecision::Passthrough);
+
+        let before = key(&input);
+        std::fs::write(&target, "fn main() { std::process::exit(1) }").unwrap();
+        assert_ne!(key(&input), before);
+
+        // The same relative input from another project is a different decision
+        let mut elsewhere = edit_input(Path::new(target.file_name().unwrap()));
+        elsewhere.cwd = "/home/user/other-project".to_string();
+        assert_ne!(key(&elsewhere), key(&input));
+    }
+
+    #[test]
+    fn test_query_handling_is_part_of_the_key() {
+        let input = edit_input(Path::new("/home/user/project/src/main.rs"));
+        let passthrough = cache_key(&input, false, QueryDecision::Passthrough);
+        assert_ne!(cache_key(&input, true, QueryDecision::Passthrough), passthrough);
+        assert_ne!(cache_key(&input, false, QueryDecision::Deny), passthrough);
+        assert_eq!(cache_key(&input, false, QueryDecision::Passthrough), passthrough);
+    }
+
+    #[test]
+    fn test_expired_entries_are_ignored() {
+        let config = CacheConfig {
+            enabled: true,
+            file: temp_path("cache.json"),
+            ttl_secs: 0,
+        };
+        store(&config, "key", allow_entry());
+        assert!(lookup(&config, "config-a", "key").is_none());
+    }
+
+    #[test]
+    fn test_config_change_purges_cached_decisions() {
+        let config = CacheConfig {
+            enabled: true,
+            file: temp_path("cache.json"),
+            ttl_secs: 300,
+        };
+        store(&config, "key", allow_entry());
+        assert!(lookup(&config, "config-a", "key").is_some());
+
+        // A lookup under another config misses and purges, so going back doesn't revive it
+        assert!(lookup(&config, "config-b", "key").is_none());
+        assert!(lookup(&config, "config-a", "key").is_none());
+
+        store(&config, "key", allow_entry());
+        assert_eq!(clear(&config).unwrap(), 1);
+        assert!(lookup(&config, "config-a", "key").is_none());
+    }
+}
@@ -2,29 +2,201 @@
 #![warn(clippy::all)]
 
 use anyhow::{Context, Result};
+use chrono::{DateTime, FixedOffset, Local, NaiveTime, Utc};
 use regex::Regex;
 use serde::Deserialize;
 use std::collections::HashMap;
 use std::fs;
+use std::io::Read;
 use std::path::{Path, PathBuf};
+use std::time::SystemTime;
 use toml::{Table, Value};
 
+/// Version of the config file format, bumped when fields change incompatibly
+pub const CONFIG_SCHEMA_VERSION: u32 = 2;
+
+/// Deepest allowed include nesting, counting the top-level config file
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+/// Default for `includes.max_total_bytes`, far above any hand-written config
+const DEFAULT_MAX_CONFIG_BYTES: u64 = 16 * 1024 * 1024;
+
 #[derive(Debug, Deserialize)]
 pub struct Config {
+    // Checked against CONFIG_SCHEMA_VERSION before parsing; older configs need `migrate`
+    #[serde(default)]
+    pub schema_version: Option<u32>,
     #[serde(default)]
     pub logging: LoggingConfig,
     #[serde(default)]
     pub llm_fallback: LlmFallbackConfig,
     #[serde(default)]
     pub includes: IncludesConfig,
+    #[serde(default)]
+    pub output: OutputConfig,
+    #[serde(default)]
+    pub cache: CacheConfig,
+    #[serde(default)]
+    pub builtin: BuiltinConfig,
+    #[serde(default)]
+    pub session_approvals: SessionApprovalsConfig,
+    #[serde(default)]
+    pub session_budget: SessionBudgetConfig,
+    #[serde(default)]
+    pub matching: MatchingConfig,
+    #[serde(default)]
+    pub tool_fields: ToolFieldsMap,
+    // Independently maintained config whose deny rules can veto this config's allows
+    #[serde(default)]
+    pub secondary_config: Option<PathBuf>,
+    #[serde(default)]
+    pub rule_id_scope: RuleIdScope,
     #[serde(flatten)]
     pub sections: HashMap<String, SectionConfig>,
 }
 
+/// Where rule ids must be unique; `section` scope qualifies each id as `section/id`
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RuleIdScope {
+    #[default]
+    Global,
+    Section,
+}
+
+/// Toggles for the compiled-in rule packs, which are merged in unless disabled
 #[derive(Debug, Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct BuiltinConfig {
+    #[serde(default, rename = "debug-tools")]
+    pub debug_tools: BuiltinPackConfig,
+    #[serde(default)]
+    pub obfuscation: BuiltinPackConfig,
+    #[serde(default, rename = "self-protection")]
+    pub self_protection: BuiltinPackConfig,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BuiltinPackConfig {
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+}
+
+impl Default for BuiltinPackConfig {
+    fn default() -> Self {
+        Self { enabled: true }
+    }
+}
+
+#[derive(Debug, Deserialize)]
 pub struct IncludesConfig {
     #[serde(default)]
     pub files: Vec<String>,
+    // Cap on the bytes read across the config and all its includes; only the top-level value counts
+    #[serde(default = "default_max_config_bytes")]
+    pub max_total_bytes: u64,
+}
+
+impl Default for IncludesConfig {
+    fn default() -> Self {
+        Self {
+            files: Vec::new(),
+            max_total_bytes: default_max_config_bytes(),
+        }
+    }
+}
+
+fn default_max_config_bytes() -> u64 {
+    DEFAULT_MAX_CONFIG_BYTES
+}
+
+/// Bytes read so far while loading a config and its includes, against `includes.max_total_bytes`
+struct ReadBudget {
+    used: u64,
+    limit: u64,
+}
+
+impl ReadBudget {
+    /// Read a whole file, failing once it would take the total past the limit
+    /// Reading stops at the limit, so an endless file such as /dev/zero can't exhaust memory
+    fn read(&mut self, path: &Path) -> Result<String> {
+        let file = fs::File::open(path)
+            .with_context(|| format!("Failed to read config file: {}", path.display()))?;
+        let remaining = self.limit.saturating_sub(self.used);
+        let mut contents = String::new();
+        file.take(remaining + 1)
+            .read_to_string(&mut contents)
+            .with_context(|| format!("Failed to read config file: {}", path.display()))?;
+        self.charge(path, contents.len() as u64)?;
+        Ok(contents)
+    }
+
+    /// Count a file reused from an `IncludeCache` as if it had been read again
+    fn charge(&mut self, path: &Path, bytes: u64) -> Result<()> {
+        self.used += bytes;
+        self.check(path)
+    }
+
+    fn check(&self, path: &Path) -> Result<()> {
+        if self.used > self.limit {
+            anyhow::bail!(
+                "Config size limit exceeded at {}: the config and its includes are over \
+                 includes.max_total_bytes ({} bytes)",
+                path.display(),
+                self.limit
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Parsed config files by canonical path, with the modification time and size each was read at,
+/// so loading again re-reads and re-parses only the files that changed since
+#[derive(Default)]
+pub struct IncludeCache {
+    files: HashMap<PathBuf, CachedConfigFile>,
+}
+
+struct CachedConfigFile {
+    modified: SystemTime,
+    len: u64,
+    table: Table,
+}
+
+impl IncludeCache {
+    /// The parsed table of `path`, from the cache while the file's modification time and size
+    /// are unchanged; only regular files are cached
+    fn load(&mut self, path: &Path, canonical: &Path, budget: &mut ReadBudget) -> Result<Table> {
+        let metadata = fs::metadata(path)
+            .with_context(|| format!("Failed to read config file: {}", path.display()))?;
+        let modified = metadata.modified().ok().filter(|_| metadata.is_file());
+        if let Some(cached) = self.files.get(canonical)
+            && modified == Some(cached.modified)
+            && metadata.len() == cached.len
+        {
+            log::debug!("Config file unchanged since last load: {}", path.display());
+            budget.charge(path, cached.len)?;
+            return Ok(cached.table.clone());
+        }
+
+        let contents = budget.read(path)?;
+        let table: Table = toml::from_str(&contents)
+            .with_context(|| format!("Failed to parse TOML config: {}", path.display()))?;
+        match modified {
+            Some(modified) => {
+                let cached = CachedConfigFile {
+                    modified,
+                    len: metadata.len(),
+                    table: table.clone(),
+                };
+                self.files.insert(canonical.to_path_buf(), cached);
+            }
+            None => {
+                self.files.remove(canonical);
+            }
+        }
+        Ok(table)
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -34,10 +206,16 @@ pub struct SectionConfig {
     pub priority: u32,
     #[serde(default = "default_enabled")]
     pub enabled: bool,
+    // false: matches are logged and flagged for review but never decide anything
+    #[serde(default = "default_enabled")]
+    pub enforce: bool,
     #[serde(default)]
     pub allow: Vec<RuleConfig>,
     #[serde(default)]
     pub deny: Vec<RuleConfig>,
+    // Keys serde doesn't recognize, kept so typos can be reported instead of ignored
+    #[serde(flatten)]
+    pub unknown_keys: Table,
 }
 
 fn default_priority() -> u32 {
@@ -51,10 +229,53 @@ fn default_enabled() -> bool {
 pub struct CompiledConfig {
     pub logging: LoggingConfig,
     pub llm_fallback: LlmFallbackConfig,
+    pub output: OutputConfig,
+    pub cache: CacheConfig,
+    pub session_approvals: SessionApprovalsConfig,
+    pub session_budget: SessionBudgetConfig,
+    pub matching: MatchingConfig,
+    pub tool_fields: ToolFieldsMap,
     pub deny_rules: Vec<Rule>,
     pub allow_rules: Vec<Rule>,
+    /// One message per unrecognized section or rule key (errors under --strict)
+    pub unknown_keys: Vec<String>,
+    pub secondary: Option<SecondaryRules>,
+    /// Hash of the merged config (includes and profile applied); cached decisions are tagged with it
+    pub config_hash: String,
+}
+
+/// Deny rules of the `secondary_config`, kept apart from this config's own rules
+pub struct SecondaryRules {
+    pub path: PathBuf,
+    pub deny_rules: Vec<Rule>,
+}
+
+/// Every problem found while validating and compiling a config
+#[derive(Debug)]
+pub struct ConfigErrors(pub Vec<String>);
+
+impl ConfigErrors {
+    fn check(problems: Vec<String>) -> Result<(), ConfigErrors> {
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(ConfigErrors(problems))
+        }
+    }
+}
+
+impl std::fmt::Display for ConfigErrors {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Found {} configuration problem(s):", self.0.len())?;
+        for problem in &self.0 {
+            write!(f, "\n  - {}", problem)?;
+        }
+        Ok(())
+    }
 }
 
+impl std::error::Error for ConfigErrors {}
+
 #[derive(Debug, Deserialize)]
 pub struct LoggingConfig {
     #[serde(default = "default_log_file")]
@@ -63,6 +284,59 @@ pub struct LoggingConfig {
     pub review_log_file: PathBuf,
     #[serde(default = "default_log_level")]
     pub log_level: String,
+    // Deny any decision whose review risk level is at or above this ("low", "medium", "high")
+    #[serde(default)]
+    pub auto_deny_risk_level: Option<String>,
+    // Named pipe that also receives each decision as a JSON line (best-effort, non-blocking)
+    #[serde(default)]
+    pub decision_fifo: Option<PathBuf>,
+    // Collapse identical review entries from the same session within this many seconds
+    #[serde(default)]
+    pub review_dedup_window_secs: Option<u64>,
+    #[serde(default)]
+    pub buffer: LogBufferConfig,
+    // While this file exists every input gets the decision it contains ("deny" or "ask")
+    #[serde(default)]
+    pub override_file: Option<PathBuf>,
+    // Encoding of the operational and review logs
+    #[serde(default)]
+    pub format: LogFormat,
+    // Add total_latency_ms (whole hook run, from config load to logging) to review entries
+    #[serde(default)]
+    pub record_latency: bool,
+    // Fraction (0.0-1.0) of allows written to the operational log; everything else is always logged
+    #[serde(default = "default_sample_rate")]
+    pub operational_sample_rate: f64,
+    // Show a deny rule's description as the reason instead of the generated one
+    #[serde(default)]
+    pub use_description_as_reason: bool,
+    // With use_description_as_reason, keep the generated reason in parentheses after it
+    #[serde(default)]
+    pub description_reason_detail: bool,
+    // Collector (host:port) that also receives each review entry as an NDJSON line (best-effort)
+    #[serde(default)]
+    pub tcp_sink: Option<String>,
+    // Longest wait for the TCP sink to connect or accept a line
+    #[serde(default = "default_tcp_sink_timeout_ms")]
+    pub tcp_sink_timeout_ms: u64,
+    // Add a verbose block (rules considered, LLM prompt and responses, timing) to the review
+    // entries of each session's first this-many operations
+    #[serde(default)]
+    pub verbose_first_n: Option<u64>,
+    // Where verbose_first_n keeps its per-session operation counts
+    #[serde(default = "default_verbose_dir")]
+    pub verbose_dir: PathBuf,
+}
+
+/// On-disk encoding of the operational and review logs
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// One JSON object per line
+    #[default]
+    Json,
+    /// Fixed columns with a header row; nested values are compact JSON cells
+    Csv,
 }
 
 impl Default for LoggingConfig {
@@ -71,10 +345,53 @@ impl Default for LoggingConfig {
             log_file: default_log_file(),
             review_log_file: default_review_log_file(),
             log_level: default_log_level(),
+            auto_deny_risk_level: None,
+            decision_fifo: None,
+            review_dedup_window_secs: None,
+            buffer: LogBufferConfig::default(),
+            override_file: None,
+            format: LogFormat::default(),
+            record_latency: false,
+            operational_sample_rate: default_sample_rate(),
+            use_description_as_reason: false,
+            description_reason_detail: false,
+            tcp_sink: None,
+            tcp_sink_timeout_ms: default_tcp_sink_timeout_ms(),
+            verbose_first_n: None,
+            verbose_dir: default_verbose_dir(),
+        }
+    }
+}
+
+/// Batch log writes through a background task instead of locking the file per entry
+#[derive(Debug, Deserialize, Clone)]
+pub struct LogBufferConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "default_flush_interval_ms")]
+    pub flush_interval_ms: u64,
+    #[serde(default = "default_flush_max_entries")]
+    pub flush_max_entries: usize,
+}
+
+impl Default for LogBufferConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            flush_interval_ms: default_flush_interval_ms(),
+            flush_max_entries: default_flush_max_entries(),
         }
     }
 }
 
+fn default_flush_interval_ms() -> u64 {
+    1000
+}
+
+fn default_flush_max_entries() -> usize {
+    100
+}
+
 fn default_log_file() -> PathBuf {
     PathBuf::from("/tmp/claude-tool-use.log")
 }
@@ -87,16 +404,319 @@ fn default_log_level() -> String {
     "info".to_string()
 }
 
+fn default_sample_rate() -> f64 {
+    1.0
+}
+
+fn default_tcp_sink_timeout_ms() -> u64 {
+    200
+}
+
+fn default_verbose_dir() -> PathBuf {
+    PathBuf::from("/tmp/claude-hook-verbose")
+}
+
+/// Cache for LLM fallback decisions (rules are cheap enough to always evaluate)
+#[derive(Debug, Deserialize, Clone)]
+pub struct CacheConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "default_cache_file")]
+    pub file: PathBuf,
+    #[serde(default = "default_cache_ttl_secs")]
+    pub ttl_secs: u64,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            file: default_cache_file(),
+            ttl_secs: default_cache_ttl_secs(),
+        }
+    }
+}
+
+fn default_cache_file() -> PathBuf {
+    PathBuf::from("/tmp/claude-hook-decision-cache.json")
+}
+
+fn default_cache_ttl_secs() -> u64 {
+    3600
+}
+
+/// Bounds on rule matching for a single input
+#[derive(Debug, Deserialize, Clone)]
+pub struct MatchingConfig {
+    // Rules still being matched after this long count as non-matches and flag the input for review
+    #[serde(default = "default_match_timeout_ms")]
+    pub timeout_ms: u64,
+    // Timezone for allowed_hours: "local" (default), "UTC", or a fixed offset like "+02:00"
+    #[serde(default = "default_timezone")]
+    pub timezone: String,
+    // JSON pointer (e.g. "/params") to an object in tool_input holding wrapped arguments;
+    // fields missing at the top level of tool_input are looked up there
+    #[serde(default)]
+    pub input_root: Option<String>,
+    // How path fields and cwd are read before matching: "posix" (as-is), "windows" or "auto"
+    #[serde(default)]
+    pub path_style: PathStyle,
+}
+
+impl Default for MatchingConfig {
+    fn default() -> Self {
+        Self {
+            timeout_ms: default_match_timeout_ms(),
+            timezone: default_timezone(),
+            input_root: None,
+            path_style: PathStyle::default(),
+        }
+    }
+}
+
+/// Convention of the paths in hook input, so POSIX-style rules also match Windows paths
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PathStyle {
+    /// Paths are matched as written
+    #[default]
+    Posix,
+    /// Every path is converted: `C:\Users\me` is matched as `/c/Users/me`
+    Windows,
+    /// Only paths that look like Windows paths are converted
+    Auto,
+}
+
+fn default_timezone() -> String {
+    "local".to_string()
+}
+
+/// `[tool_fields]`: tool name -> which of its tool_input fields each rule regex applies to
+pub type ToolFieldsMap = HashMap<String, ToolFields>;
+
+/// Input fields of one tool, by the rule regex that matches them
+/// e.g. `NotebookEdit = { file_path = "notebook_path", content = "new_source" }`
+/// A tool listed here is matched only through these fields, replacing any built-in handling
+#[derive(Debug, Deserialize, Clone, Default, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct ToolFields {
+    pub file_path: Option<String>,
+    pub command: Option<String>,
+    pub prompt: Option<String>,
+    pub content: Option<String>,
+}
+
+impl ToolFields {
+    /// (match kind, input field) for each mapped field, in a fixed order
+    pub fn mapped(&self) -> impl Iterator<Item = (&'static str, &str)> {
+        [
+            ("file_path", &self.file_path),
+            ("command", &self.command),
+            ("prompt", &self.prompt),
+            ("content", &self.content),
+        ]
+        .into_iter()
+        .filter_map(|(kind, field)| Some((kind, field.as_deref()?)))
+    }
+}
+
+/// Fixed offset for a `matching.timezone` value, None for "local"; Err if unrecognized
+fn parse_timezone(timezone: &str) -> Result<Option<FixedOffset>, ()> {
+    match timezone {
+        "local" => Ok(None),
+        "UTC" | "utc" | "Z" => Ok(FixedOffset::east_opt(0)),
+        offset => offset.parse::<FixedOffset>().map(Some).map_err(|_| ()),
+    }
+}
+
+impl MatchingConfig {
+    /// Time of day that `allowed_hours` windows are checked against
+    /// An invalid timezone is rejected at load time, so it falls back to local here
+    pub fn time_of_day(&self, now: DateTime<Utc>) -> NaiveTime {
+        match parse_timezone(&self.timezone) {
+            Ok(Some(offset)) => now.with_timezone(&offset).time(),
+            _ => now.with_timezone(&Local).time(),
+        }
+    }
+}
+
+fn default_match_timeout_ms() -> u64 {
+    1000
+}
+
+/// Per-session file approvals that override deny decisions (see `approve`)
+#[derive(Debug, Deserialize, Clone)]
+pub struct SessionApprovalsConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "default_approvals_dir")]
+    pub dir: PathBuf,
+}
+
+impl Default for SessionApprovalsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            dir: default_approvals_dir(),
+        }
+    }
+}
+
+/// Under the user's home rather than /tmp, where another account could create it first
+fn default_approvals_dir() -> PathBuf {
+    match std::env::var_os("HOME") {
+        Some(home) => PathBuf::from(home).join(".local/state/claude-hook-approvals"),
+        None => std::env::temp_dir().join("claude-hook-approvals"),
+    }
+}
+
+/// Per-session cap on tool calls (see `session_budget::count_operation`)
+#[derive(Debug, Deserialize, Clone)]
+pub struct SessionBudgetConfig {
+    // Deny every operation after this many in one session; unset disables the budget
+    #[serde(default)]
+    pub max_operations_per_session: Option<u64>,
+    #[serde(default = "default_budget_dir")]
+    pub dir: PathBuf,
+    // Start a session's count over this long after its first counted operation
+    #[serde(default)]
+    pub reset_after_secs: Option<u64>,
+}
+
+impl Default for SessionBudgetConfig {
+    fn default() -> Self {
+        Self {
+            max_operations_per_session: None,
+            dir: default_budget_dir(),
+            reset_after_secs: None,
+        }
+    }
+}
+
+fn default_budget_dir() -> PathBuf {
+    PathBuf::from("/tmp/claude-hook-budget")
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct OutputConfig {
+    #[serde(default)]
+    pub schema: OutputSchema,
+    // Only used by the claude-code schema
+    #[serde(default)]
+    pub keys: OutputKeys,
+    #[serde(default)]
+    pub stream: OutputStream,
+    #[serde(default)]
+    pub passthrough_output: PassthroughOutput,
+    // Also emit source, rule_id, matched_pattern and risk_level as a structured object
+    #[serde(default)]
+    pub reason_detail: bool,
+    // Append the hook run's trace id (also in both log entries) to the emitted reason
+    #[serde(default)]
+    pub include_trace_id: bool,
+}
+
+/// What a passthrough emits; Claude Code treats no output as "use the normal permission flow"
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PassthroughOutput {
+    /// Write nothing
+    #[default]
+    None,
+    /// Emit an explicit allow
+    Allow,
+    /// Emit an explicit ask, so the user is prompted
+    Ask,
+}
+
+/// Stream the decision JSON is written to
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputStream {
+    #[default]
+    Stdout,
+    /// For wrappers that reserve stdout for other data
+    Stderr,
+}
+
+/// Shape of the emitted decision JSON
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum OutputSchema {
+    /// Nested `hookSpecificOutput` object that Claude Code reads
+    #[default]
+    ClaudeCode,
+    /// Flat `{"decision": ..., "reason": ...}` for other permission consumers
+    Simple,
+}
+
+/// JSON key names used when emitting a decision
+/// Defaults match the current Claude Code hook schema; override only if that schema changes
+#[derive(Debug, Deserialize, Clone)]
+pub struct OutputKeys {
+    #[serde(default = "default_hook_specific_output_key")]
+    pub hook_specific_output: String,
+    #[serde(default = "default_hook_event_name_key")]
+    pub hook_event_name: String,
+    #[serde(default = "default_permission_decision_key")]
+    pub permission_decision: String,
+    #[serde(default = "default_permission_decision_reason_key")]
+    pub permission_decision_reason: String,
+    #[serde(default = "default_suppress_output_key")]
+    pub suppress_output: String,
+}
+
+impl Default for OutputKeys {
+    fn default() -> Self {
+        Self {
+            hook_specific_output: default_hook_specific_output_key(),
+            hook_event_name: default_hook_event_name_key(),
+            permission_decision: default_permission_decision_key(),
+            permission_decision_reason: default_permission_decision_reason_key(),
+            suppress_output: default_suppress_output_key(),
+        }
+    }
+}
+
+fn default_hook_specific_output_key() -> String {
+    "hookSpecificOutput".to_string()
+}
+
+fn default_hook_event_name_key() -> String {
+    "hookEventName".to_string()
+}
+
+fn default_permission_decision_key() -> String {
+    "permissionDecision".to_string()
+}
+
+fn default_permission_decision_reason_key() -> String {
+    "permissionDecisionReason".to_string()
+}
+
+fn default_suppress_output_key() -> String {
+    "suppressOutput".to_string()
+}
+
 #[derive(Debug, Deserialize, Clone)]
 pub struct LlmFallbackConfig {
     #[serde(default)]
     pub enabled: bool,
     // REQUIRED when enabled=true - no default to avoid silent misconfigurations
     pub endpoint: Option<String>,
-    // REQUIRED when enabled=true - no default to avoid silent misconfigurations
+    // REQUIRED when enabled=true (unless `models` is set) - no default to avoid silent misconfigurations
     pub model: Option<String>,
+    // Ask several models concurrently and combine their verdicts per `consensus`
+    #[serde(default)]
+    pub models: Vec<String>,
+    #[serde(default)]
+    pub consensus: Consensus,
+    // Literal key, or a reference resolved at load time: "env:VAR" or "file:/path"
     #[serde(default)]
     pub api_key: Option<String>,
+    // Read the key from this file at load time (relative paths are against the config file)
+    #[serde(default)]
+    pub api_key_file: Option<PathBuf>,
     #[serde(default = "default_timeout_secs")]
     pub timeout_secs: u64,
     #[serde(default = "default_temperature")]
@@ -107,9 +727,120 @@ pub struct LlmFallbackConfig {
     pub system_prompt: String,
     #[serde(default)]
     pub provider_preferences: Option<Vec<String>>,
+    // Skip simple_json_repair so malformed responses fail (and retry) instead of being patched
+    #[serde(default)]
+    pub strict_json: bool,
+    // Keep every attempt's raw response and parse outcome in the review log's llm_metadata
+    #[serde(default)]
+    pub record_attempts: bool,
+    // Keep the prompt sent in llm_metadata; set at run time for logging.verbose_first_n operations
+    #[serde(skip)]
+    pub record_prompt: bool,
+    // TESTING ONLY: return this fixed verdict instead of calling the endpoint
+    #[serde(default)]
+    pub mock_response: Option<MockLlmResponse>,
+    // What a QUERY verdict does outside test mode
+    #[serde(default)]
+    pub query_decision: QueryDecision,
+    // Include this many earlier decisions from the same session in the prompt (0 = off)
+    #[serde(default)]
+    pub include_decision_history: usize,
+    #[serde(default = "default_decision_history_dir")]
+    pub decision_history_dir: PathBuf,
+    // Reject plaintext http:// endpoints, which would send tool inputs unencrypted
+    #[serde(default)]
+    pub require_tls: bool,
+    // With require_tls, still accept http:// to localhost, 127.0.0.1 or [::1]
+    #[serde(default)]
+    pub allow_localhost_http: bool,
+}
+
+/// Outcome of an LLM QUERY verdict in production
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum QueryDecision {
+    /// Emit no decision so Claude Code falls back to its normal permission flow
+    #[default]
+    Passthrough,
+    /// Emit an explicit "ask" so the user is always prompted
+    Ask,
+    /// Block the call outright
+    Deny,
+}
+
+/// How verdicts from several `models` combine into one
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Consensus {
+    /// Allow only when every model allows
+    #[default]
+    All,
+    /// Allow when more than half of the models allow
+    Majority,
+}
+
+impl std::fmt::Display for Consensus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Consensus::All => write!(f, "all"),
+            Consensus::Majority => write!(f, "majority"),
+        }
+    }
+}
+
+/// Canned LLM verdict for CI and development, used in place of a network call
+#[derive(Debug, Deserialize, Clone)]
+pub struct MockLlmResponse {
+    pub classification: String,
+    pub reasoning: String,
+    #[serde(default)]
+    pub concerns: Vec<String>,
 }
 
 impl LlmFallbackConfig {
+    /// Replace `api_key_file` and `env:`/`file:` references with the key itself
+    /// Skipped when disabled, so an unset variable doesn't break configs that don't use the LLM
+    pub fn resolve_api_key(&mut self, base_dir: &Path) -> Result<()> {
+        if !self.enabled {
+            return Ok(());
+        }
+
+        let key = match (self.api_key.take(), self.api_key_file.take()) {
+            (Some(_), Some(_)) => {
+                anyhow::bail!("llm_fallback: set either 'api_key' or 'api_key_file', not both")
+            }
+            (None, Some(file)) => Some(read_api_key_file(&base_dir.join(file))?),
+            (Some(key), None) => Some(match (key.strip_prefix("env:"), key.strip_prefix("file:")) {
+                (Some(var), _) => std::env::var(var).map_err(|_| {
+                    anyhow::anyhow!("llm_fallback.api_key references environment variable '{}', which is not set", var)
+                })?,
+                (_, Some(file)) => read_api_key_file(&base_dir.join(file))?,
+                _ => key,
+            }),
+            (None, None) => None,
+        };
+        self.api_key = key;
+        Ok(())
+    }
+
+    /// Models to ask: `models` when set, otherwise the single `model`
+    pub fn model_names(&self) -> Vec<String> {
+        if self.models.is_empty() {
+            self.model.iter().cloned().collect()
+        } else {
+            self.models.clone()
+        }
+    }
+
+    /// Model name recorded in LLM metadata when the answer doesn't name one
+    /// (several models are listed comma-separated)
+    pub fn model_label(&self) -> String {
+        if self.mock_response.is_some() {
+            return "mock_response".to_string();
+        }
+        self.model_names().join(", ")
+    }
+
     /// Validate LLM fallback configuration
     /// Returns detailed error messages if enabled but misconfigured
     pub fn validate(&self) -> Result<()> {
@@ -117,6 +848,28 @@ impl LlmFallbackConfig {
             return Ok(());
         }
 
+        // A zero timeout fails every call before a request is even sent
+        if self.timeout_secs == 0 {
+            anyhow::bail!("Invalid llm_fallback.timeout_secs 0 - must be at least 1 second");
+        }
+        if !(0.0..=2.0).contains(&self.temperature) {
+            anyhow::bail!(
+                "Invalid llm_fallback.temperature {} - must be between 0.0 and 2.0",
+                self.temperature
+            );
+        }
+
+        // A mock response never touches the network, so no endpoint or model is needed
+        if let Some(mock) = &self.mock_response {
+            if !matches!(mock.classification.to_uppercase().as_str(), "ALLOW" | "QUERY") {
+                anyhow::bail!(
+                    "Invalid llm_fallback.mock_response classification '{}' - must be ALLOW or QUERY",
+                    mock.classification
+                );
+            }
+            return Ok(());
+        }
+
         // When enabled, endpoint and model are REQUIRED
         if self.endpoint.is_none() {
             anyhow::bail!(
@@ -126,7 +879,11 @@ impl LlmFallbackConfig {
             );
         }
 
-        if self.model.is_none() {
+        if self.model.is_some() && !self.models.is_empty() {
+            anyhow::bail!("LLM fallback: set either 'model' or 'models', not both");
+        }
+
+        if self.model.is_none() && self.models.is_empty() {
             anyhow::bail!(
                 "LLM fallback is enabled but 'model' is not specified.\n\
                  Please add: model = \"anthropic/claude-haiku-4.5\" (for OpenRouter)\n\
@@ -142,9 +899,50 @@ impl LlmFallbackConfig {
                 endpoint
             );
         }
+        self.check_endpoint_tls(endpoint)?;
 
         Ok(())
     }
+
+    /// Under `require_tls`, refuse any endpoint that isn't https://
+    /// (plain http to a loopback host is let through with `allow_localhost_http`)
+    pub fn check_endpoint_tls(&self, endpoint: &str) -> Result<()> {
+        if !self.require_tls || endpoint.starts_with("https://") {
+            return Ok(());
+        }
+        if self.allow_localhost_http
+            && let Some(rest) = endpoint.strip_prefix("http://")
+            && is_loopback_host(rest)
+        {
+            return Ok(());
+        }
+        anyhow::bail!(
+            "LLM endpoint '{}' is not https:// and llm_fallback.require_tls is set{}",
+            endpoint,
+            if self.allow_localhost_http { "" } else { " (allow_localhost_http exempts localhost)" }
+        )
+    }
+}
+
+/// Whether the authority at the start of `rest` (an http URL without its scheme) is a loopback host
+fn is_loopback_host(rest: &str) -> bool {
+    let authority = rest.split(['/', '?', '#']).next().unwrap_or_default();
+    let host_port = authority.rsplit_once('@').map_or(authority, |(_, host)| host);
+    let host = match host_port.strip_prefix('[') {
+        Some(bracketed) => bracketed.split(']').next().unwrap_or_default(),
+        None => host_port.split(':').next().unwrap_or_default(),
+    };
+    matches!(host.to_ascii_lowercase().as_str(), "localhost" | "127.0.0.1" | "::1")
+}
+
+fn read_api_key_file(path: &Path) -> Result<String> {
+    let contents = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read llm_fallback API key file: {}", path.display()))?;
+    let key = contents.trim();
+    if key.is_empty() {
+        anyhow::bail!("llm_fallback API key file is empty: {}", path.display());
+    }
+    Ok(key.to_string())
 }
 
 impl Default for LlmFallbackConfig {
@@ -153,16 +951,32 @@ impl Default for LlmFallbackConfig {
             enabled: false,
             endpoint: None,
             model: None,
+            models: Vec::new(),
+            consensus: Consensus::default(),
             api_key: None,
+            api_key_file: None,
             timeout_secs: default_timeout_secs(),
             temperature: default_temperature(),
             max_retries: default_max_retries(),
             system_prompt: default_system_prompt(),
             provider_preferences: None,
+            strict_json: false,
+            record_attempts: false,
+            record_prompt: false,
+            mock_response: None,
+            query_decision: QueryDecision::default(),
+            include_decision_history: 0,
+            decision_history_dir: default_decision_history_dir(),
+            require_tls: false,
+            allow_localhost_http: false,
         }
     }
 }
 
+fn default_decision_history_dir() -> PathBuf {
+    PathBuf::from("/tmp/claude-hook-history")
+}
+
 fn default_timeout_secs() -> u64 {
     60
 }
@@ -220,16 +1034,19 @@ CLASSIFICATION RULES (apply in this order):
 }
 
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Default)]
 pub struct RuleConfig {
     // REQUIRED - validation will check this
     pub id: String,
     #[serde(default)]
     pub description: Option<String>,
 
+    // Exact tool name; a `*` matches any run of characters, e.g. "mcp__github__*"
     pub tool: Option<String>,
     pub tool_regex: Option<String>,
     pub tool_exclude_regex: Option<String>,
+    // Matches every tool of this MCP server (the <server> in mcp__<server>__<tool>)
+    pub mcp_server: Option<String>,
     pub file_path_regex: Option<String>,
     pub file_path_exclude_regex: Option<String>,
     pub command_regex: Option<String>,
@@ -238,9 +1055,153 @@ pub struct RuleConfig {
     pub subagent_type_exclude_regex: Option<String>,
     pub prompt_regex: Option<String>,
     pub prompt_exclude_regex: Option<String>,
+    pub content_regex: Option<String>,
+    pub content_exclude_regex: Option<String>,
+    pub raw_input_regex: Option<String>,
+    pub raw_input_exclude_regex: Option<String>,
+    // BashOutput/KillBash: the background shell's bash_id
+    pub bash_id_regex: Option<String>,
+    pub bash_id_exclude_regex: Option<String>,
+    // Bash: the description Claude gives alongside the command (deny rules only, since the
+    // description is model-written and says nothing certain about what the command does)
+    pub description_regex: Option<String>,
+    pub description_exclude_regex: Option<String>,
+    // Top-level HookInput fields outside the known ones, by name -> regex; all must match
+    #[serde(default)]
+    pub context_regex: HashMap<String, String>,
+    // file_path must be inside (true) or outside (false) the project root found from cwd
+    pub within_project: Option<bool>,
+    // Rule only applies while the time of day is within "HH:MM-HH:MM" (may wrap past midnight)
+    pub allowed_hours: Option<String>,
+    // Rule only applies when the input's permission_mode is one of these (e.g. "bypassPermissions")
+    #[serde(default)]
+    pub permission_mode: Vec<String>,
+    // Rule is only compiled in when this environment variable is truthy
+    pub enabled_env: Option<String>,
+    #[serde(default)]
+    pub on_match: OnMatch,
+    // Policy context added to the LLM prompt when this rule escalates, or when an input
+    // of a tool this rule selects falls through to the LLM fallback
+    pub llm_hint: Option<String>,
+    // Match command_regex against each statement of a chained/multiline command (deny rules only)
+    #[serde(default)]
+    pub split_statements: bool,
+    // Bash: match file_path_regex against the command's path-like arguments (deny rules only)
+    #[serde(default)]
+    pub match_path_args: bool,
+    // Flag allows from this rule for review (rule allows are otherwise pre-approved)
+    #[serde(default)]
+    pub review: bool,
+    // Allow rules only: fsync the review log entry before allowing, and deny if that fails
+    #[serde(default)]
+    pub require_audit_flush: bool,
+    // Allow rules only: allow only if the LLM also answers ALLOW; otherwise the rule doesn't allow
+    #[serde(default)]
+    pub require_llm_concur: bool,
+    // Bash: match when the command has more shell metacharacters than this (deny rules only)
+    pub max_shell_metachars: Option<usize>,
+    // Read: match the first content_preview_bytes (default 4096) of the target file (deny rules only)
+    pub content_preview_regex: Option<String>,
+    pub content_preview_bytes: Option<usize>,
+    // Bash: match commands that decode and run an encoded payload, e.g. `base64 -d | sh` (deny rules only)
+    #[serde(default)]
+    pub detect_obfuscation: bool,
+    // Bash: match when a statement runs sudo, doas, su or pkexec (deny rules only)
+    #[serde(default)]
+    pub requires_elevation: bool,
+    // Match file paths (and Bash path arguments) in the built-in sensitive set: system dirs,
+    // credential files, shell rc files (deny rules only)
+    #[serde(default)]
+    pub sensitive_paths: bool,
+    // Glob/Grep: match searches that recurse from a system or home root, or start above
+    // the project root (deny rules only)
+    #[serde(default)]
+    pub deny_broad_glob: bool,
+    // Bash: match when an output redirection (`>`, `>>`, `2>`) targets a matching path (deny rules only)
+    pub redirect_target_regex: Option<String>,
+    // JSON pointer to a number in tool_input; matches when it falls outside min/max (deny rules only)
+    pub input_number: Option<String>,
+    pub input_number_min: Option<f64>,
+    pub input_number_max: Option<f64>,
+    // JSON pointer to a string in tool_input; matches when its Shannon entropy (bits per
+    // character) reaches field_min_entropy and its length exceeds field_max_length,
+    // whichever are set (deny rules only)
+    pub measure_field: Option<String>,
+    pub field_min_entropy: Option<f64>,
+    pub field_max_length: Option<usize>,
+    // Deny rules only: skip this rule while the env var holds this exact value
+    // bypass_env defaults to HOOK_CONFIRM_<RULE_ID> (uppercased, '-' as '_')
+    pub bypass_env: Option<String>,
+    pub bypass_value: Option<String>,
+    // Deny rules only: safer alternative appended to the reason as "Suggestion: ..."
+    pub suggestion: Option<String>,
+    // Filesystem requirements on the target file_path (allow rules only); a violation denies
+    #[serde(default)]
+    pub path_must_not_be_symlink: bool,
+    #[serde(default)]
+    pub path_must_exist: bool,
+    #[serde(default)]
+    pub path_must_not_exist: bool,
+    // Allow rules only: file_path must be absolute with no `.`/`..` components, or the allow denies
+    #[serde(default)]
+    pub require_absolute: bool,
+    // Deny rules only: match relative file_paths (narrowed by file_path_regex when set)
+    #[serde(default)]
+    pub deny_relative: bool,
+    // Self-tests run by `validate`: values the rule's field patterns must / must not match
+    #[serde(default)]
+    pub examples_match: Vec<String>,
+    #[serde(default)]
+    pub examples_no_match: Vec<String>,
+    // Keys serde doesn't recognize, e.g. a misspelled `file_path_rgex`
+    #[serde(flatten)]
+    pub unknown_keys: Table,
+}
+
+/// What happens when a rule matches
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum OnMatch {
+    /// Apply the rule's own allow/deny decision
+    #[default]
+    Decide,
+    /// Ask the LLM for a second opinion and use its verdict
+    Llm,
+}
+
+/// Daily time window `start`..`end`; wraps past midnight when `end` is earlier than `start`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HourWindow {
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+}
+
+impl HourWindow {
+    /// Parse "HH:MM-HH:MM"; an empty window (start == end) is rejected as ambiguous
+    pub fn parse(s: &str) -> Option<Self> {
+        let (start, end) = s.split_once('-')?;
+        let start = NaiveTime::parse_from_str(start.trim(), "%H:%M").ok()?;
+        let end = NaiveTime::parse_from_str(end.trim(), "%H:%M").ok()?;
+        (start != end).then_some(HourWindow { start, end })
+    }
+
+    /// Start inclusive, end exclusive
+    pub fn contains(&self, time: NaiveTime) -> bool {
+        if self.start < self.end {
+            self.start <= time && time < self.end
+        } else {
+            time >= self.start || time < self.end
+        }
+    }
 }
 
-#[derive(Debug, Clone)]
+impl std::fmt::Display for HourWindow {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}-{}", self.start.format("%H:%M"), self.end.format("%H:%M"))
+    }
+}
+
+#[derive(Debug, Clone, Default)]
 pub struct Rule {
     pub id: String,
     pub section_name: String,
@@ -249,6 +1210,7 @@ pub struct Rule {
     pub tool: Option<String>,
     pub tool_regex: Option<Regex>,
     pub tool_exclude_regex: Option<Regex>,
+    pub mcp_server: Option<String>,
     pub file_path_regex: Option<Regex>,
     pub file_path_exclude_regex: Option<Regex>,
     pub command_regex: Option<Regex>,
@@ -257,65 +1219,669 @@ pub struct Rule {
     pub subagent_type_exclude_regex: Option<Regex>,
     pub prompt_regex: Option<Regex>,
     pub prompt_exclude_regex: Option<Regex>,
+    pub content_regex: Option<Regex>,
+    pub content_exclude_regex: Option<Regex>,
+    pub raw_input_regex: Option<Regex>,
+    pub raw_input_exclude_regex: Option<Regex>,
+    pub bash_id_regex: Option<Regex>,
+    pub bash_id_exclude_regex: Option<Regex>,
+    pub description_regex: Option<Regex>,
+    pub description_exclude_regex: Option<Regex>,
+    /// Sorted by field name
+    pub context_regex: Vec<(String, Regex)>,
+    pub within_project: Option<bool>,
+    pub allowed_hours: Option<HourWindow>,
+    pub permission_mode: Vec<String>,
+    pub on_match: OnMatch,
+    pub llm_hint: Option<String>,
+    pub split_statements: bool,
+    pub match_path_args: bool,
+    pub review: bool,
+    pub require_audit_flush: bool,
+    pub require_llm_concur: bool,
+    pub max_shell_metachars: Option<usize>,
+    pub content_preview_regex: Option<Regex>,
+    pub content_preview_bytes: usize,
+    pub detect_obfuscation: bool,
+    pub requires_elevation: bool,
+    pub sensitive_paths: bool,
+    pub deny_broad_glob: bool,
+    pub redirect_target_regex: Option<Regex>,
+    pub input_number: Option<String>,
+    pub input_number_min: Option<f64>,
+    pub input_number_max: Option<f64>,
+    pub measure_field: Option<String>,
+    pub field_min_entropy: Option<f64>,
+    pub field_max_length: Option<usize>,
+    /// (env var, expected value) that lets a human temporarily bypass this deny rule
+    pub bypass: Option<(String, String)>,
+    pub suggestion: Option<String>,
+    pub path_must_not_be_symlink: bool,
+    pub path_must_exist: bool,
+    pub path_must_not_exist: bool,
+    pub require_absolute: bool,
+    pub deny_relative: bool,
+    pub examples_match: Vec<String>,
+    pub examples_no_match: Vec<String>,
+    /// From a section with `enforce = false`: a match is recorded, then matching continues
+    pub audit_only: bool,
+}
+
+impl CompiledConfig {
+    /// Problems that don't stop the config from loading but are almost certainly mistakes
+    pub fn lint_warnings(&self) -> Vec<String> {
+        let rules = || self.deny_rules.iter().chain(&self.allow_rules);
+        rules()
+            .flat_map(self_defeating_excludes)
+            .chain(rules().flat_map(unknown_permission_modes))
+            .chain(self.timeout_budget_warning())
+            .collect()
+    }
+
+    /// Warn when rule matching plus the LLM call can outlast Claude Code's hook timeout,
+    /// which would cancel the hook before the LLM's verdict arrives
+    fn timeout_budget_warning(&self) -> Option<String> {
+        if !self.llm_fallback.enabled || self.llm_fallback.mock_response.is_some() {
+            return None;
+        }
+        let worst_case_ms = self
+            .matching
+            .timeout_ms
+            .saturating_add(self.llm_fallback.timeout_secs.saturating_mul(1000));
+        (worst_case_ms >= CLAUDE_CODE_HOOK_TIMEOUT_SECS * 1000).then(|| {
+            format!(
+                "matching.timeout_ms ({}) plus llm_fallback.timeout_secs ({}s) reaches Claude Code's default \
+                 {}s hook timeout - the hook can be cancelled before the LLM answers; lower timeout_secs \
+                 or raise the hook's `timeout` in settings.json",
+                self.matching.timeout_ms, self.llm_fallback.timeout_secs, CLAUDE_CODE_HOOK_TIMEOUT_SECS
+            )
+        })
+    }
+
+    /// Rules whose `examples_match` / `examples_no_match` expectations don't hold
+    pub fn example_failures(&self) -> Vec<String> {
+        self.deny_rules
+            .iter()
+            .chain(&self.allow_rules)
+            .flat_map(rule_example_failures)
+            .collect()
+    }
+}
+
+impl Rule {
+    /// Each field's main and exclude pattern, by field name
+    pub fn field_patterns(&self) -> [(&'static str, &Option<Regex>, &Option<Regex>); 8] {
+        [
+            ("tool", &self.tool_regex, &self.tool_exclude_regex),
+            ("file_path", &self.file_path_regex, &self.file_path_exclude_regex),
+            ("command", &self.command_regex, &self.command_exclude_regex),
+            ("prompt", &self.prompt_regex, &self.prompt_exclude_regex),
+            ("content", &self.content_regex, &self.content_exclude_regex),
+            ("raw_input", &self.raw_input_regex, &self.raw_input_exclude_regex),
+            ("bash_id", &self.bash_id_regex, &self.bash_id_exclude_regex),
+            ("description", &self.description_regex, &self.description_exclude_regex),
+        ]
+    }
+}
+
+/// How long Claude Code lets a hook run unless its settings.json entry sets `timeout`
+const CLAUDE_CODE_HOOK_TIMEOUT_SECS: u64 = 60;
+
+/// Permission modes Claude Code sends today; others are warned about, not rejected,
+/// so a newly added mode can be used before this list catches up
+const KNOWN_PERMISSION_MODES: &[&str] = &["default", "plan", "acceptEdits", "bypassPermissions"];
+
+fn unknown_permission_modes(rule: &Rule) -> Vec<String> {
+    rule.permission_mode
+        .iter()
+        .filter(|mode| !KNOWN_PERMISSION_MODES.contains(&mode.as_str()))
+        .map(|mode| {
+            format!(
+                "Rule '{}' in section '{}': permission_mode '{}' is not one of {}, so the rule may never apply",
+                rule.id,
+                rule.section_name,
+                mode,
+                KNOWN_PERMISSION_MODES.join(", ")
+            )
+        })
+        .collect()
+}
+
+/// An example matches when some input-field pattern of the rule (not the tool pattern)
+/// matches it and that field's exclude doesn't
+/// Tool, context and time conditions aren't part of the check
+fn rule_example_failures(rule: &Rule) -> Vec<String> {
+    if rule.examples_match.is_empty() && rule.examples_no_match.is_empty() {
+        return Vec::new();
+    }
+    let fields: Vec<_> = rule
+        .field_patterns()
+        .into_iter()
+        .filter(|(field, main, _)| *field != "tool" && main.is_some())
+        .collect();
+    if fields.is_empty() {
+        return vec![format!(
+            "Rule '{}' in section '{}': has examples but no field regex to test them against",
+            rule.id, rule.section_name
+        )];
+    }
+
+    let matching_field = |example: &str| {
+        fields.iter().find_map(|(field, main, exclude)| {
+            let main = main.as_ref()?;
+            let excluded = exclude.as_ref().is_some_and(|e| e.is_match(example));
+            (main.is_match(example) && !excluded).then(|| format!("{}_regex '{}'", field, main))
+        })
+    };
+
+    let mut failures = Vec::new();
+    for example in rule.examples_match.iter().filter(|e| matching_field(e).is_none()) {
+        failures.push(format!(
+            "Rule '{}' in section '{}': example '{}' should match but doesn't",
+            rule.id, rule.section_name, example
+        ));
+    }
+    for example in &rule.examples_no_match {
+        if let Some(pattern) = matching_field(example) {
+            failures.push(format!(
+                "Rule '{}' in section '{}': example '{}' should not match but matches {}",
+                rule.id, rule.section_name, example, pattern
+            ));
+        }
+    }
+    failures
+}
+
+/// Exclude patterns that remove everything their main pattern matches, so the rule
+/// can never fire: the same pattern, or one that matches any string
+/// Real superset detection is undecidable in general; these are the common slips
+fn self_defeating_excludes(rule: &Rule) -> Vec<String> {
+    const MATCH_ANYTHING: &[&str] = &["", ".*", "^.*", "^.*$", "(?s).*", "(?s)^.*$"];
+
+    rule.field_patterns()
+        .into_iter()
+        .filter_map(|(field, main, exclude)| {
+            let (main, exclude) = (main.as_ref()?.as_str(), exclude.as_ref()?.as_str());
+            (main == exclude || MATCH_ANYTHING.contains(&exclude)).then(|| {
+                format!(
+                    "Rule '{}' in section '{}': {}_exclude_regex '{}' excludes everything {}_regex '{}' matches, so the rule never matches",
+                    rule.id, rule.section_name, field, exclude, field, main
+                )
+            })
+        })
+        .collect()
 }
 
 impl Config {
     pub fn load_from_file(path: &Path) -> Result<CompiledConfig> {
-        let merged_toml = Self::load_with_includes(path)?;
+        Self::load_from_file_with_profile(path, None)
+    }
+
+    /// Load a config, overlaying `[profiles.<profile>]` on the base settings when given
+    pub fn load_from_file_with_profile(path: &Path, profile: Option<&str>) -> Result<CompiledConfig> {
+        Self::load(path, profile, true, &mut IncludeCache::default())
+    }
 
-        let config: Config = toml::from_str(&merged_toml.to_string())
+    /// Load as `load_from_file_with_profile` does, reusing each file (root, includes and
+    /// secondary config) that `cache` holds unchanged; the merge and compile always run again,
+    /// so the result is the same as a full load
+    pub fn load_cached(
+        path: &Path,
+        profile: Option<&str>,
+        cache: &mut IncludeCache,
+    ) -> Result<CompiledConfig> {
+        Self::load(path, profile, true, cache)
+    }
+
+    /// A secondary config is loaded with `allow_secondary` off, so it can't chain to another
+    fn load(
+        path: &Path,
+        profile: Option<&str>,
+        allow_secondary: bool,
+        cache: &mut IncludeCache,
+    ) -> Result<CompiledConfig> {
+        let (mut merged_toml, loaded_files) = Self::load_with_includes(path, cache)?;
+
+        let schema_version = crate::migrate::detect_schema_version(&merged_toml)?;
+        if schema_version != CONFIG_SCHEMA_VERSION {
+            anyhow::bail!(
+                "Config {} uses schema version {} but this hook expects {}. \
+                 Run `claude-code-permissions-hook migrate --input {}` to upgrade it",
+                path.display(),
+                schema_version,
+                CONFIG_SCHEMA_VERSION,
+                path.display()
+            );
+        }
+
+        let profiles = merged_toml.remove("profiles");
+        if let Some(name) = profile {
+            merged_toml = Self::apply_profile(merged_toml, profiles, name)?;
+        }
+
+        let merged_str = merged_toml.to_string();
+        let mut config: Config = toml::from_str(&merged_str)
             .with_context(|| format!("Failed to parse TOML config: {}", path.display()))?;
+        config.add_builtin_packs()?;
+        let base_dir = path.parent().unwrap_or_else(|| Path::new("."));
+        config.llm_fallback.resolve_api_key(base_dir)?;
 
-        config.validate()?;
-        config.compile()
+        let secondary_path = config.secondary_config.take().map(|secondary| base_dir.join(secondary));
+        if let Some(secondary) = &secondary_path
+            && !allow_secondary
+        {
+            anyhow::bail!(
+                "Secondary config {} cannot set secondary_config itself (found {})",
+                path.display(),
+                secondary.display()
+            );
+        }
+
+        if config.builtin.self_protection.enabled {
+            // The config path as given too, in case it is a symlink to the canonical file
+            let mut protected: Vec<PathBuf> = std::path::absolute(path).into_iter().collect();
+            protected.extend(loaded_files);
+            protected.extend(secondary_path.clone());
+            protected.extend(std::env::current_exe().ok());
+            // Writing an approval there would lift a deny from inside the session
+            let protected_dirs: Vec<PathBuf> = match config.session_approvals.enabled {
+                true => std::path::absolute(&config.session_approvals.dir).into_iter().collect(),
+                false => Vec::new(),
+            };
+            let (name, section) = crate::builtin::self_protection_section(&protected, &protected_dirs)?;
+            config.sections.insert(name, section);
+        }
+
+        // Run validation and compilation together so every problem is reported at once
+        let validation = config.validate();
+        let compiled = config.compile();
+        let mut compiled = match (validation, compiled) {
+            (Ok(()), Ok(compiled)) => compiled,
+            (validation, compiled) => {
+                let mut problems = validation.err().map(|e| e.0).unwrap_or_default();
+                problems.extend(compiled.err().map(|e| e.0).unwrap_or_default());
+                return Err(ConfigErrors(problems).into());
+            }
+        };
+        compiled.config_hash = config_hash(&merged_str);
+
+        // Only the deny rules are used, so the two rulesets stay independent
+        if let Some(secondary_path) = secondary_path {
+            let secondary = Self::load(&secondary_path, None, false, cache)
+                .with_context(|| format!("Failed to load secondary_config: {}", secondary_path.display()))?;
+            compiled.secondary = Some(SecondaryRules {
+                path: secondary_path,
+                deny_rules: secondary.deny_rules,
+            });
+        }
+        Ok(compiled)
+    }
+
+    /// Merge the enabled built-in rule packs in as ordinary sections
+    fn add_builtin_packs(&mut self) -> Result<()> {
+        let mut user_sections: Vec<&String> = self.sections.keys().collect();
+        user_sections.sort();
+        if let Some(name) = user_sections.into_iter().find(|name| crate::builtin::is_builtin_section(name)) {
+            anyhow::bail!(
+                "Invalid section name '{}' - the 'builtin-' prefix is reserved for built-in rule packs",
+                name
+            );
+        }
+
+        for (name, section) in crate::builtin::enabled_sections(&self.builtin)? {
+            self.sections.insert(name, section);
+        }
+        Ok(())
     }
 
-    fn validate(&self) -> Result<()> {
-        const RESERVED_NAMES: &[&str] = &["logging", "llm_fallback", "includes"];
+    fn validate(&self) -> Result<(), ConfigErrors> {
+        const RESERVED_NAMES: &[&str] = &[
+            "logging",
+            "llm_fallback",
+            "includes",
+            "output",
+            "cache",
+            "profiles",
+            "builtin",
+            "session_approvals",
+            "session_budget",
+            "matching",
+            "tool_fields",
+        ];
         let kebab_case_regex = Regex::new(r"^[a-z][a-z0-9-]*$").unwrap();
+        let mut problems = Vec::new();
+
+        // Sort section names so problems are reported in a stable order
+        let mut section_names: Vec<&String> = self.sections.keys().collect();
+        section_names.sort();
 
         // Check for reserved section names
         for reserved in RESERVED_NAMES {
             if self.sections.contains_key(*reserved) {
-                anyhow::bail!(
-                    "Invalid section name '{}' - this is a reserved name. \
-                     Reserved names: logging, llm_fallback, includes",
-                    reserved
-                );
+                problems.push(format!(
+                    "Invalid section name '{}' - this is a reserved name. Reserved names: {}",
+                    reserved,
+                    RESERVED_NAMES.join(", ")
+                ));
             }
         }
 
         // Validate kebab-case section names
-        for section_name in self.sections.keys() {
+        for section_name in &section_names {
             if !kebab_case_regex.is_match(section_name) {
-                anyhow::bail!(
+                problems.push(format!(
                     "Invalid section name '{}' - section names must be kebab-case \
                      (lowercase letters, numbers, and hyphens only, starting with a letter). \
                      Example: 'build-tools', 'file-operations'",
                     section_name
-                );
+                ));
             }
         }
 
-        // Validate rule ID uniqueness globally
+        if let Some(level) = &self.logging.auto_deny_risk_level
+            && crate::logging::risk_level_rank(level).is_none()
+        {
+            problems.push(format!(
+                "Invalid logging.auto_deny_risk_level '{}' - must be one of: low, medium, high",
+                level
+            ));
+        }
+
+        if parse_timezone(&self.matching.timezone).is_err() {
+            problems.push(format!(
+                "Invalid matching.timezone '{}' - must be \"local\", \"UTC\" or an offset like \"+02:00\"",
+                self.matching.timezone
+            ));
+        }
+
+        if let Some(root) = &self.matching.input_root
+            && !root.starts_with('/')
+        {
+            problems.push(format!(
+                "Invalid matching.input_root '{}' - must be a JSON pointer starting with '/', such as \"/params\"",
+                root
+            ));
+        }
+
+        if self.session_budget.max_operations_per_session == Some(0) {
+            problems.push("Invalid session_budget.max_operations_per_session 0 - must be at least 1".to_string());
+        }
+        if self.session_budget.reset_after_secs == Some(0) {
+            problems.push("Invalid session_budget.reset_after_secs 0 - must be at least 1".to_string());
+        }
+
+        let mut mapped_tools: Vec<(&String, &ToolFields)> = self.tool_fields.iter().collect();
+        mapped_tools.sort_by_key(|(tool, _)| *tool);
+        for (tool, fields) in mapped_tools {
+            if fields.mapped().next().is_none() {
+                problems.push(format!("tool_fields.{} maps no fields - set file_path, command, prompt or content", tool));
+            }
+            for (kind, field) in fields.mapped().filter(|(_, field)| field.is_empty()) {
+                problems.push(format!("tool_fields.{}.{} must name an input field, not '{}'", tool, kind, field));
+            }
+        }
+
+        if let Some(address) = &self.logging.tcp_sink
+            && !address.rsplit_once(':').is_some_and(|(host, port)| !host.is_empty() && port.parse::<u16>().is_ok())
+        {
+            problems.push(format!("Invalid logging.tcp_sink '{}' - must be host:port", address));
+        }
+
+        if !(0.0..=1.0).contains(&self.logging.operational_sample_rate) {
+            problems.push(format!(
+                "Invalid logging.operational_sample_rate {} - must be between 0.0 and 1.0",
+                self.logging.operational_sample_rate
+            ));
+        }
+
+        if self.logging.format == LogFormat::Csv && self.logging.review_dedup_window_secs.is_some() {
+            problems.push(
+                "logging.review_dedup_window_secs rewrites JSON lines in place and requires logging.format = \"json\""
+                    .to_string(),
+            );
+        }
+
+        // Per-statement matching fires on any statement, and the threshold conditions fire
+        // on unusual input, so they are only safe on deny rules
+        for section_name in &section_names {
+            for rule in &self.sections[*section_name].allow {
+                let deny_only = [
+                    ("split_statements", rule.split_statements),
+                    ("match_path_args", rule.match_path_args),
+                    ("deny_relative", rule.deny_relative),
+                    ("max_shell_metachars", rule.max_shell_metachars.is_some()),
+                    ("detect_obfuscation", rule.detect_obfuscation),
+                    ("requires_elevation", rule.requires_elevation),
+                    ("sensitive_paths", rule.sensitive_paths),
+                    ("deny_broad_glob", rule.deny_broad_glob),
+                    ("description_regex", rule.description_regex.is_some()),
+                    ("redirect_target_regex", rule.redirect_target_regex.is_some()),
+                    ("content_preview_regex", rule.content_preview_regex.is_some()),
+                    ("input_number", rule.input_number.is_some()),
+                    ("measure_field", rule.measure_field.is_some()),
+                    ("bypass_value", rule.bypass_value.is_some()),
+                    ("suggestion", rule.suggestion.is_some()),
+                ];
+                for (option, _) in deny_only.iter().filter(|(_, set)| *set) {
+                    problems.push(format!(
+                        "Rule '{}' in section '{}': {} is only supported on deny rules",
+                        rule.id, section_name, option
+                    ));
+                }
+            }
+        }
+
+        for section_name in &section_names {
+            for rule in self.sections[*section_name].deny.iter() {
+                match (&rule.bypass_env, &rule.bypass_value) {
+                    (Some(_), None) => problems.push(format!(
+                        "Rule '{}' in section '{}': bypass_env requires bypass_value",
+                        rule.id, section_name
+                    )),
+                    (_, Some(value)) if value.is_empty() => problems.push(format!(
+                        "Rule '{}' in section '{}': bypass_value must not be empty",
+                        rule.id, section_name
+                    )),
+                    _ => {}
+                }
+                if rule.match_path_args && rule.file_path_regex.is_none() {
+                    problems.push(format!(
+                        "Rule '{}' in section '{}': match_path_args requires file_path_regex",
+                        rule.id, section_name
+                    ));
+                }
+                match (&rule.content_preview_regex, rule.content_preview_bytes) {
+                    (None, Some(_)) => problems.push(format!(
+                        "Rule '{}' in section '{}': content_preview_bytes requires content_preview_regex",
+                        rule.id, section_name
+                    )),
+                    (_, Some(0)) => problems.push(format!(
+                        "Rule '{}' in section '{}': content_preview_bytes must be at least 1",
+                        rule.id, section_name
+                    )),
+                    _ => {}
+                }
+            }
+        }
+
+        for section_name in &section_names {
+            let section = &self.sections[*section_name];
+            for rule in section.deny.iter().chain(section.allow.iter()) {
+                let Some(pointer) = &rule.input_number else {
+                    continue;
+                };
+                if !pointer.starts_with('/') {
+                    problems.push(format!(
+                        "Rule '{}' in section '{}': input_number '{}' must be a JSON pointer starting with '/'",
+                        rule.id, section_name, pointer
+                    ));
+                }
+                match (rule.input_number_min, rule.input_number_max) {
+                    (None, None) => problems.push(format!(
+                        "Rule '{}' in section '{}': input_number needs input_number_min and/or input_number_max",
+                        rule.id, section_name
+                    )),
+                    (Some(min), Some(max)) if min > max => problems.push(format!(
+                        "Rule '{}' in section '{}': input_number_min {} is greater than input_number_max {}",
+                        rule.id, section_name, min, max
+                    )),
+                    _ => {}
+                }
+            }
+        }
+
+        for section_name in &section_names {
+            let section = &self.sections[*section_name];
+            for rule in section.deny.iter().chain(section.allow.iter()) {
+                let thresholds = rule.field_min_entropy.is_some() || rule.field_max_length.is_some();
+                match &rule.measure_field {
+                    None if thresholds => problems.push(format!(
+                        "Rule '{}' in section '{}': field_min_entropy and field_max_length require measure_field",
+                        rule.id, section_name
+                    )),
+                    None => {}
+                    Some(pointer) => {
+                        if !pointer.starts_with('/') {
+                            problems.push(format!(
+                                "Rule '{}' in section '{}': measure_field '{}' must be a JSON pointer starting with '/'",
+                                rule.id, section_name, pointer
+                            ));
+                        }
+                        if !thresholds {
+                            problems.push(format!(
+                                "Rule '{}' in section '{}': measure_field needs field_min_entropy and/or field_max_length",
+                                rule.id, section_name
+                            ));
+                        }
+                    }
+                }
+                if let Some(entropy) = rule.field_min_entropy
+                    && !(entropy > 0.0 && entropy.is_finite())
+                {
+                    problems.push(format!(
+                        "Rule '{}' in section '{}': field_min_entropy must be a positive number of bits per character",
+                        rule.id, section_name
+                    ));
+                }
+            }
+        }
+
+        // With a Bash detector set, command_regex only narrows it, leaving no statements to split
+        for section_name in &section_names {
+            for rule in self.sections[*section_name].deny.iter().filter(|r| r.split_statements) {
+                let detectors = [
+                    ("redirect_target_regex", rule.redirect_target_regex.is_some()),
+                    ("match_path_args", rule.match_path_args),
+                    ("max_shell_metachars", rule.max_shell_metachars.is_some()),
+                    ("detect_obfuscation", rule.detect_obfuscation),
+                    ("requires_elevation", rule.requires_elevation),
+                ];
+                for (option, _) in detectors.iter().filter(|(_, set)| *set) {
+                    problems.push(format!(
+                        "Rule '{}' in section '{}': split_statements cannot be combined with {} - use separate rules",
+                        rule.id, section_name, option
+                    ));
+                }
+            }
+        }
+
+        // Path requirements turn an allow into a deny, so they mean nothing on deny rules
+        for section_name in &section_names {
+            let section = &self.sections[*section_name];
+            for rule in &section.deny {
+                if rule.path_must_not_be_symlink || rule.path_must_exist || rule.path_must_not_exist {
+                    problems.push(format!(
+                        "Rule '{}' in section '{}': path_must_* requirements are only supported on allow rules",
+                        rule.id, section_name
+                    ));
+                }
+                if rule.require_audit_flush {
+                    problems.push(format!(
+                        "Rule '{}' in section '{}': require_audit_flush is only supported on allow rules",
+                        rule.id, section_name
+                    ));
+                }
+                if rule.require_llm_concur {
+                    problems.push(format!(
+                        "Rule '{}' in section '{}': require_llm_concur is only supported on allow rules",
+                        rule.id, section_name
+                    ));
+                }
+                if rule.require_absolute {
+                    problems.push(format!(
+                        "Rule '{}' in section '{}': require_absolute is only supported on allow rules (use deny_relative on deny rules)",
+                        rule.id, section_name
+                    ));
+                }
+            }
+            for rule in section.allow.iter().filter(|r| r.require_llm_concur && r.on_match == OnMatch::Llm) {
+                problems.push(format!(
+                    "Rule '{}' in section '{}': require_llm_concur cannot be combined with on_match = \"llm\"",
+                    rule.id, section_name
+                ));
+            }
+            for rule in section.allow.iter().filter(|r| r.path_must_exist && r.path_must_not_exist) {
+                problems.push(format!(
+                    "Rule '{}' in section '{}': path_must_exist and path_must_not_exist are mutually exclusive",
+                    rule.id, section_name
+                ));
+            }
+        }
+
+        // Validate rule ID uniqueness, globally or per section depending on rule_id_scope
         let mut seen_ids = std::collections::HashSet::new();
-        for (section_name, section) in &self.sections {
+        for section_name in &section_names {
+            let section = &self.sections[*section_name];
+            if self.rule_id_scope == RuleIdScope::Section {
+                seen_ids.clear();
+            }
             for rule in section.deny.iter().chain(section.allow.iter()) {
                 if !seen_ids.insert(&rule.id) {
-                    anyhow::bail!(
-                        "Duplicate rule ID '{}' in section '{}'. \
-                         Rule IDs must be unique across all sections.",
-                        rule.id,
-                        section_name
-                    );
+                    problems.push(match self.rule_id_scope {
+                        RuleIdScope::Global => format!(
+                            "Duplicate rule ID '{}' in section '{}'. \
+                             Rule IDs must be unique across all sections.",
+                            rule.id, section_name
+                        ),
+                        RuleIdScope::Section => format!(
+                            "Duplicate rule ID '{}' in section '{}'. \
+                             Rule IDs must be unique within a section (rule_id_scope = \"section\").",
+                            rule.id, section_name
+                        ),
+                    });
+                }
+            }
+        }
+
+        ConfigErrors::check(problems)
+    }
+
+    /// Unrecognized keys in sections and rules, in stable order
+    fn unknown_key_messages(&self) -> Vec<String> {
+        let mut section_names: Vec<&String> = self.sections.keys().collect();
+        section_names.sort();
+
+        let mut messages = Vec::new();
+        for section_name in section_names {
+            let section = &self.sections[section_name];
+            for key in section.unknown_keys.keys() {
+                messages.push(format!("Unknown key '{}' in section '{}'", key, section_name));
+            }
+            for rule in section.deny.iter().chain(&section.allow) {
+                for key in rule.unknown_keys.keys() {
+                    messages.push(format!(
+                        "Unknown key '{}' in rule '{}' (section '{}')",
+                        key, rule.id, section_name
+                    ));
                 }
             }
         }
-
-        Ok(())
+        messages
     }
 
-    fn compile(self) -> Result<CompiledConfig> {
+    fn compile(self) -> Result<CompiledConfig, ConfigErrors> {
+        let unknown_keys = self.unknown_key_messages();
+
         // Collect sections with their names and sort by priority
         let mut sections: Vec<(String, SectionConfig)> = self.sections.into_iter()
             .filter(|(_, section)| section.enabled)
@@ -327,77 +1893,211 @@ impl Config {
                 .then_with(|| name_a.cmp(name_b))
         });
 
+        let mut problems = Vec::new();
+
         // Flatten deny rules in priority order
         let mut deny_rules = Vec::new();
         for (section_name, section) in &sections {
-            for rule_config in &section.deny {
-                let rule = compile_rule(rule_config, section_name)?;
-                deny_rules.push(rule);
+            for rule_config in section.deny.iter().filter(|r| rule_enabled_by_env(r)) {
+                match compile_rule(rule_config, section_name, self.rule_id_scope) {
+                    Ok(rule) => deny_rules.push(Rule { audit_only: !section.enforce, ..rule }),
+                    Err(e) => problems.extend(e.0),
+                }
             }
         }
 
         // Flatten allow rules in priority order
         let mut allow_rules = Vec::new();
         for (section_name, section) in &sections {
-            for rule_config in &section.allow {
-                let rule = compile_rule(rule_config, section_name)?;
-                allow_rules.push(rule);
+            for rule_config in section.allow.iter().filter(|r| rule_enabled_by_env(r)) {
+                match compile_rule(rule_config, section_name, self.rule_id_scope) {
+                    Ok(rule) => allow_rules.push(Rule { audit_only: !section.enforce, ..rule }),
+                    Err(e) => problems.extend(e.0),
+                }
             }
         }
 
+        ConfigErrors::check(problems)?;
+
         Ok(CompiledConfig {
             logging: self.logging,
             llm_fallback: self.llm_fallback,
+            output: self.output,
+            cache: self.cache,
+            session_approvals: self.session_approvals,
+            session_budget: self.session_budget,
+            matching: self.matching,
+            tool_fields: self.tool_fields,
             deny_rules,
             allow_rules,
+            unknown_keys,
+            secondary: None,
+            config_hash: String::new(),
         })
     }
 
-    fn load_with_includes(path: &Path) -> Result<Table> {
-        let contents = fs::read_to_string(path)
-            .with_context(|| format!("Failed to read config file: {}", path.display()))?;
+    /// The merged table, and the canonical path of every file that went into it
+    fn load_with_includes(path: &Path, cache: &mut IncludeCache) -> Result<(Table, Vec<PathBuf>)> {
+        let mut loaded = Vec::new();
+        let mut budget = ReadBudget {
+            used: 0,
+            limit: DEFAULT_MAX_CONFIG_BYTES,
+        };
+        let table = Self::load_with_include_chain(path, &mut Vec::new(), &mut loaded, &mut budget, cache)?;
+        Ok((table, loaded))
+    }
 
-        let mut toml_table: Table = toml::from_str(&contents)
-            .with_context(|| format!("Failed to parse TOML config: {}", path.display()))?;
+    /// `chain` holds the canonical paths of the files currently being loaded,
+    /// so a file may be included twice from different branches but never from itself
+    /// `loaded` collects every file read, once each; `budget` counts every read, repeats included
+    /// `cache` supplies files unchanged since an earlier load; include globs are expanded afresh
+    fn load_with_include_chain(
+        path: &Path,
+        chain: &mut Vec<PathBuf>,
+        loaded: &mut Vec<PathBuf>,
+        budget: &mut ReadBudget,
+        cache: &mut IncludeCache,
+    ) -> Result<Table> {
+        fs::metadata(path).with_context(|| format!("Failed to read config file: {}", path.display()))?;
+        let canonical = fs::canonicalize(path)
+            .with_context(|| format!("Failed to resolve config file path: {}", path.display()))?;
+        let describe_chain = |chain: &[PathBuf], last: &Path| {
+            chain.iter()
+                .map(|p| p.display().to_string())
+                .chain(std::iter::once(last.display().to_string()))
+                .collect::<Vec<_>>()
+                .join(" -> ")
+        };
+        if chain.contains(&canonical) {
+            anyhow::bail!("Include cycle detected: {}", describe_chain(chain, &canonical));
+        }
+        if chain.len() >= MAX_INCLUDE_DEPTH {
+            anyhow::bail!(
+                "Include depth exceeds {}: {}",
+                MAX_INCLUDE_DEPTH,
+                describe_chain(chain, &canonical)
+            );
+        }
+
+        let mut toml_table = cache.load(path, &canonical, budget)?;
 
         let base_dir = path.parent().unwrap_or_else(|| Path::new("."));
 
+        // The top-level file sets the cap before any include is read
+        if chain.is_empty()
+            && let Some(Value::Table(includes_section)) = toml_table.get("includes")
+            && let Some(Value::Integer(limit)) = includes_section.get("max_total_bytes")
+        {
+            budget.limit = u64::try_from(*limit).unwrap_or(0);
+            budget.check(path)?;
+        }
+
         // Collect include paths first to avoid borrow checker issues
-        let include_paths: Vec<PathBuf> = if let Some(Value::Table(includes_section)) = toml_table.get("includes") {
-            if let Some(Value::Array(files)) = includes_section.get("files") {
-                files.iter()
-                    .filter_map(|file_value| {
-                        if let Value::String(include_path) = file_value {
-                            // Resolve path: absolute if starts with /, relative to base_dir otherwise
-                            Some(if include_path.starts_with('/') {
-                                PathBuf::from(include_path)
-                            } else {
-                                base_dir.join(include_path)
-                            })
-                        } else {
-                            None
-                        }
-                    })
-                    .collect()
-            } else {
-                Vec::new()
+        let mut include_paths: Vec<PathBuf> = Vec::new();
+        if let Some(Value::Table(includes_section)) = toml_table.get("includes")
+            && let Some(Value::Array(files)) = includes_section.get("files")
+        {
+            for file_value in files {
+                if let Value::String(include_path) = file_value {
+                    include_paths.extend(Self::expand_include(base_dir, include_path)?);
+                }
             }
-        } else {
-            Vec::new()
-        };
+        }
 
         // Now load and merge includes
+        if !loaded.contains(&canonical) {
+            loaded.push(canonical.clone());
+        }
+        chain.push(canonical);
         for include_file in include_paths {
-            let include_table = Self::load_with_includes(&include_file)
+            let include_table = Self::load_with_include_chain(&include_file, chain, loaded, budget, cache)
                 .with_context(|| format!("Failed to load included file: {}", include_file.display()))?;
 
             // Merge include_table into toml_table, with toml_table taking precedence
             Self::merge_tables(&mut toml_table, include_table);
         }
+        chain.pop();
 
         Ok(toml_table)
     }
 
+    /// Resolve an `includes.files` entry: absolute if it starts with /, relative to base_dir otherwise
+    /// An entry with glob characters (`*`, `?`, `[`) expands to the files it matches, sorted by path
+    /// A glob that matches nothing only warns, so an empty `rules.d/` still loads
+    fn expand_include(base_dir: &Path, include_path: &str) -> Result<Vec<PathBuf>> {
+        let resolved = if include_path.starts_with('/') {
+            PathBuf::from(include_path)
+        } else {
+            base_dir.join(include_path)
+        };
+        if !include_path.contains(['*', '?', '[']) {
+            return Ok(vec![resolved]);
+        }
+
+        // The including file's directory is taken literally, even if its name has glob characters
+        let pattern = if include_path.starts_with('/') || base_dir.as_os_str().is_empty() {
+            include_path.to_string()
+        } else {
+            let base = base_dir
+                .to_str()
+                .with_context(|| format!("Config directory {} is not valid UTF-8", base_dir.display()))?;
+            format!("{}/{}", glob::Pattern::escape(base), include_path)
+        };
+        let mut matches = glob::glob(&pattern)
+            .with_context(|| format!("Invalid include glob '{}'", include_path))?
+            .collect::<Result<Vec<PathBuf>, _>>()
+            .with_context(|| format!("Failed to expand include glob '{}'", include_path))?;
+        matches.retain(|path| path.is_file());
+        matches.sort();
+
+        if matches.is_empty() {
+            log::warn!("Include glob '{}' matched no files", resolved.display());
+        }
+        Ok(matches)
+    }
+
+    /// Merge the named profile over the base table, profile values winning
+    /// A profile may override the settings tables and existing rule sections only
+    fn apply_profile(base: Table, profiles: Option<Value>, name: &str) -> Result<Table> {
+        let mut profiles = match profiles {
+            Some(Value::Table(profiles)) => profiles,
+            Some(_) => anyhow::bail!("[profiles] must be a table of named profiles"),
+            None => Table::new(),
+        };
+
+        let mut available: Vec<&String> = profiles.keys().collect();
+        available.sort();
+        let available = available.iter().map(|s| s.as_str()).collect::<Vec<_>>().join(", ");
+
+        let mut profile = match profiles.remove(name) {
+            Some(Value::Table(profile)) => profile,
+            Some(_) => anyhow::bail!("Profile '{}' must be a table", name),
+            None => anyhow::bail!("Unknown profile '{}'. Available profiles: {}", name, available),
+        };
+
+        let mut problems = Vec::new();
+        let mut keys: Vec<&String> = profile.keys().collect();
+        keys.sort();
+        for key in keys {
+            match key.as_str() {
+                "logging" | "llm_fallback" | "output" | "cache" | "session_approvals" | "session_budget"
+                | "matching" | "tool_fields" => {}
+                "includes" | "profiles" | "secondary_config" | "rule_id_scope" => {
+                    problems.push(format!("Profile '{}' cannot override '{}'", name, key))
+                }
+                section if !matches!(base.get(section), Some(Value::Table(_))) => problems.push(
+                    format!("Profile '{}' references unknown section '{}'", name, section),
+                ),
+                _ => {}
+            }
+        }
+        ConfigErrors::check(problems)?;
+
+        // merge_tables keeps existing values, so merging base into the profile lets the profile win
+        Self::merge_tables(&mut profile, base);
+        Ok(profile)
+    }
+
     fn merge_tables(base: &mut Table, other: Table) {
         for (key, value) in other {
             match (base.get_mut(&key), value) {
@@ -417,92 +2117,154 @@ impl Config {
     }
 }
 
-fn compile_rule(rule_config: &RuleConfig, section_name: &str) -> Result<Rule> {
-    // Validate XOR: exactly one of tool or tool_regex must be specified
-    match (&rule_config.tool, &rule_config.tool_regex) {
-        (Some(_), Some(_)) => anyhow::bail!(
-            "Rule '{}' in section '{}' cannot have both 'tool' and 'tool_regex'",
+/// Any change to a setting of the merged config gives a new hash, even one that can't change a decision
+/// Comments and formatting are lost in the merge, so they don't
+fn config_hash(merged_toml: &str) -> String {
+    stable_hash(&[merged_toml])
+}
+
+/// 64-bit FNV-1a of `parts` as hex, with a 0xff byte (never valid UTF-8) between parts
+/// For hashes kept on disk: unlike std's DefaultHasher, the algorithm can't change between
+/// Rust releases and silently invalidate what was stored
+pub fn stable_hash(parts: &[&str]) -> String {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0100_0000_01b3;
+
+    let separated = parts.iter().enumerate().flat_map(|(idx, part)| {
+        let separator = (idx > 0).then_some(0xff_u8);
+        separator.into_iter().chain(part.bytes())
+    });
+    let hash = separated.fold(OFFSET_BASIS, |hash, byte| (hash ^ byte as u64).wrapping_mul(PRIME));
+    format!("{:016x}", hash)
+}
+
+/// `HOOK_CONFIRM_<RULE_ID>` with the id uppercased and hyphens (and scope slashes) as underscores
+fn default_bypass_env(rule_id: &str) -> String {
+    format!("HOOK_CONFIRM_{}", rule_id.to_uppercase().replace(['-', '/'], "_"))
+}
+
+/// The id a compiled rule carries into logs, metadata and traces
+fn effective_rule_id(rule_id: &str, section_name: &str, scope: RuleIdScope) -> String {
+    match scope {
+        RuleIdScope::Global => rule_id.to_string(),
+        RuleIdScope::Section => format!("{}/{}", section_name, rule_id),
+    }
+}
+
+/// A rule with `enabled_env` is only active when that variable is set to a truthy value
+/// Section `enabled = false` always wins: its rules are dropped before this is checked
+fn rule_enabled_by_env(rule_config: &RuleConfig) -> bool {
+    let Some(var) = &rule_config.enabled_env else {
+        return true;
+    };
+    let enabled = std::env::var(var).is_ok_and(|value| env_value_is_truthy(&value));
+    if !enabled {
+        log::debug!("Rule '{}' disabled - {} is not set to a truthy value", rule_config.id, var);
+    }
+    enabled
+}
+
+fn env_value_is_truthy(value: &str) -> bool {
+    !matches!(
+        value.trim().to_lowercase().as_str(),
+        "" | "0" | "false" | "no" | "off"
+    )
+}
+
+/// Anchored regex for a `tool` wildcard: `*` matches anything, every other character itself
+fn wildcard_regex(pattern: &str) -> Regex {
+    let parts: Vec<String> = pattern.split('*').map(regex::escape).collect();
+    Regex::new(&format!("^{}$", parts.join(".*"))).expect("escaped wildcard is a valid regex")
+}
+
+fn compile_rule(
+    rule_config: &RuleConfig,
+    section_name: &str,
+    scope: RuleIdScope,
+) -> Result<Rule, ConfigErrors> {
+    let mut problems = Vec::new();
+    let id = effective_rule_id(&rule_config.id, section_name, scope);
+
+    // Validate XOR: exactly one of tool, tool_regex or mcp_server must be specified
+    let selectors = [
+        ("tool", rule_config.tool.is_some()),
+        ("tool_regex", rule_config.tool_regex.is_some()),
+        ("mcp_server", rule_config.mcp_server.is_some()),
+    ];
+    let set: Vec<&str> = selectors.iter().filter(|(_, set)| *set).map(|(name, _)| *name).collect();
+    match set.as_slice() {
+        [] => problems.push(format!(
+            "Rule '{}' in section '{}' must have either 'tool', 'tool_regex' or 'mcp_server'",
             rule_config.id,
             section_name
-        ),
-        (None, None) => anyhow::bail!(
-            "Rule '{}' in section '{}' must have either 'tool' or 'tool_regex'",
+        )),
+        [_] => {}
+        _ => problems.push(format!(
+            "Rule '{}' in section '{}' cannot combine '{}'",
             rule_config.id,
-            section_name
-        ),
-        _ => {}
-    }
-
-    let tool_regex = rule_config
-        .tool_regex
-        .as_ref()
-        .map(|s| Regex::new(s))
-        .transpose()
-        .with_context(|| format!("Invalid tool_regex in rule '{}' (section '{}')", rule_config.id, section_name))?;
-
-    let tool_exclude_regex = rule_config
-        .tool_exclude_regex
-        .as_ref()
-        .map(|s| Regex::new(s))
-        .transpose()
-        .with_context(|| format!("Invalid tool_exclude_regex in rule '{}' (section '{}')", rule_config.id, section_name))?;
-
-    let file_path_regex = rule_config
-        .file_path_regex
-        .as_ref()
-        .map(|s| Regex::new(s))
-        .transpose()
-        .with_context(|| format!("Invalid file_path_regex in rule '{}' (section '{}')", rule_config.id, section_name))?;
-
-    let file_path_exclude_regex = rule_config
-        .file_path_exclude_regex
-        .as_ref()
-        .map(|s| Regex::new(s))
-        .transpose()
-        .with_context(|| format!("Invalid file_path_exclude_regex in rule '{}' (section '{}')", rule_config.id, section_name))?;
-
-    let command_regex = rule_config
-        .command_regex
-        .as_ref()
-        .map(|s| Regex::new(s))
-        .transpose()
-        .with_context(|| format!("Invalid command_regex in rule '{}' (section '{}')", rule_config.id, section_name))?;
-
-    let command_exclude_regex = rule_config
-        .command_exclude_regex
-        .as_ref()
-        .map(|s| Regex::new(s))
-        .transpose()
-        .with_context(|| format!("Invalid command_exclude_regex in rule '{}' (section '{}')", rule_config.id, section_name))?;
-
-    let subagent_type_exclude_regex = rule_config
-        .subagent_type_exclude_regex
-        .as_ref()
-        .map(|s| Regex::new(s))
-        .transpose()
-        .with_context(|| format!("Invalid subagent_type_exclude_regex in rule '{}' (section '{}')", rule_config.id, section_name))?;
-
-    let prompt_regex = rule_config
-        .prompt_regex
-        .as_ref()
-        .map(|s| Regex::new(s))
-        .transpose()
-        .with_context(|| format!("Invalid prompt_regex in rule '{}' (section '{}')", rule_config.id, section_name))?;
-
-    let prompt_exclude_regex = rule_config
-        .prompt_exclude_regex
-        .as_ref()
-        .map(|s| Regex::new(s))
-        .transpose()
-        .with_context(|| format!("Invalid prompt_exclude_regex in rule '{}' (section '{}')", rule_config.id, section_name))?;
+            section_name,
+            set.join("' and '")
+        )),
+    }
+
+    let mut regex = |field: &str, pattern: &Option<String>| {
+        compile_regex(field, pattern, rule_config, section_name, &mut problems)
+    };
+
+    // A wildcard tool selects through the equivalent anchored tool_regex instead
+    let (tool, tool_regex) = match &rule_config.tool {
+        Some(pattern) if pattern.contains('*') => (None, Some(wildcard_regex(pattern))),
+        tool => (tool.clone(), regex("tool_regex", &rule_config.tool_regex)),
+    };
+    let tool_exclude_regex = regex("tool_exclude_regex", &rule_config.tool_exclude_regex);
+    let file_path_regex = regex("file_path_regex", &rule_config.file_path_regex);
+    let file_path_exclude_regex = regex("file_path_exclude_regex", &rule_config.file_path_exclude_regex);
+    let command_regex = regex("command_regex", &rule_config.command_regex);
+    let command_exclude_regex = regex("command_exclude_regex", &rule_config.command_exclude_regex);
+    let subagent_type_exclude_regex = regex("subagent_type_exclude_regex", &rule_config.subagent_type_exclude_regex);
+    let prompt_regex = regex("prompt_regex", &rule_config.prompt_regex);
+    let prompt_exclude_regex = regex("prompt_exclude_regex", &rule_config.prompt_exclude_regex);
+    let content_regex = regex("content_regex", &rule_config.content_regex);
+    let content_exclude_regex = regex("content_exclude_regex", &rule_config.content_exclude_regex);
+    let raw_input_regex = regex("raw_input_regex", &rule_config.raw_input_regex);
+    let raw_input_exclude_regex = regex("raw_input_exclude_regex", &rule_config.raw_input_exclude_regex);
+    let bash_id_regex = regex("bash_id_regex", &rule_config.bash_id_regex);
+    let bash_id_exclude_regex = regex("bash_id_exclude_regex", &rule_config.bash_id_exclude_regex);
+    let description_regex = regex("description_regex", &rule_config.description_regex);
+    let description_exclude_regex = regex("description_exclude_regex", &rule_config.description_exclude_regex);
+    let content_preview_regex = regex("content_preview_regex", &rule_config.content_preview_regex);
+    let redirect_target_regex = regex("redirect_target_regex", &rule_config.redirect_target_regex);
+    let mut context_fields: Vec<&String> = rule_config.context_regex.keys().collect();
+    context_fields.sort();
+    let context_regex: Vec<(String, Regex)> = context_fields
+        .into_iter()
+        .filter_map(|field| {
+            let pattern = Some(rule_config.context_regex[field].clone());
+            regex(&format!("context_regex.{}", field), &pattern).map(|r| (field.clone(), r))
+        })
+        .collect();
+
+    let allowed_hours = rule_config.allowed_hours.as_ref().and_then(|hours| {
+        let window = HourWindow::parse(hours);
+        if window.is_none() {
+            problems.push(format!(
+                "Invalid allowed_hours '{}' in rule '{}' (section '{}') - expected HH:MM-HH:MM with different start and end",
+                hours, rule_config.id, section_name
+            ));
+        }
+        window
+    });
+
+    ConfigErrors::check(problems)?;
 
     Ok(Rule {
-        id: rule_config.id.clone(),
+        id: id.clone(),
         section_name: section_name.to_string(),
         description: rule_config.description.clone(),
-        tool: rule_config.tool.clone(),
+        tool,
         tool_regex,
         tool_exclude_regex,
+        mcp_server: rule_config.mcp_server.clone(),
         file_path_regex,
         file_path_exclude_regex,
         command_regex,
@@ -511,9 +2273,82 @@ fn compile_rule(rule_config: &RuleConfig, section_name: &str) -> Result<Rule> {
         subagent_type_exclude_regex,
         prompt_regex,
         prompt_exclude_regex,
+        content_regex,
+        content_exclude_regex,
+        raw_input_regex,
+        raw_input_exclude_regex,
+        bash_id_regex,
+        bash_id_exclude_regex,
+        description_regex,
+        description_exclude_regex,
+        context_regex,
+        within_project: rule_config.within_project,
+        allowed_hours,
+        permission_mode: rule_config.permission_mode.clone(),
+        on_match: rule_config.on_match,
+        llm_hint: rule_config.llm_hint.clone(),
+        split_statements: rule_config.split_statements,
+        match_path_args: rule_config.match_path_args,
+        require_absolute: rule_config.require_absolute,
+        deny_relative: rule_config.deny_relative,
+        examples_match: rule_config.examples_match.clone(),
+        examples_no_match: rule_config.examples_no_match.clone(),
+        review: rule_config.review,
+        require_audit_flush: rule_config.require_audit_flush,
+        require_llm_concur: rule_config.require_llm_concur,
+        max_shell_metachars: rule_config.max_shell_metachars,
+        content_preview_regex,
+        content_preview_bytes: rule_config.content_preview_bytes.unwrap_or(DEFAULT_CONTENT_PREVIEW_BYTES),
+        detect_obfuscation: rule_config.detect_obfuscation,
+        requires_elevation: rule_config.requires_elevation,
+        sensitive_paths: rule_config.sensitive_paths,
+        deny_broad_glob: rule_config.deny_broad_glob,
+        redirect_target_regex,
+        input_number: rule_config.input_number.clone(),
+        input_number_min: rule_config.input_number_min,
+        input_number_max: rule_config.input_number_max,
+        measure_field: rule_config.measure_field.clone(),
+        field_min_entropy: rule_config.field_min_entropy,
+        field_max_length: rule_config.field_max_length,
+        audit_only: false,
+        bypass: rule_config.bypass_value.as_ref().map(|value| {
+            let var = rule_config
+                .bypass_env
+                .clone()
+                .unwrap_or_else(|| default_bypass_env(&id));
+            (var, value.clone())
+        }),
+        suggestion: rule_config.suggestion.clone(),
+        path_must_not_be_symlink: rule_config.path_must_not_be_symlink,
+        path_must_exist: rule_config.path_must_exist,
+        path_must_not_exist: rule_config.path_must_not_exist,
     })
 }
 
+/// How much of a file `content_preview_regex` sees when content_preview_bytes isn't set
+const DEFAULT_CONTENT_PREVIEW_BYTES: usize = 4096;
+
+/// Compile an optional regex field, recording a problem instead of failing fast
+fn compile_regex(
+    field: &str,
+    pattern: &Option<String>,
+    rule_config: &RuleConfig,
+    section_name: &str,
+    problems: &mut Vec<String>,
+) -> Option<Regex> {
+    let pattern = pattern.as_ref()?;
+    match Regex::new(pattern) {
+        Ok(regex) => Some(regex),
+        Err(e) => {
+            problems.push(format!(
+                "Invalid {} in rule '{}' (section '{}'): {}",
+                field, rule_config.id, section_name, e
+            ));
+            None
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -525,19 +2360,12 @@ mod tests {
             id: "test-read-rule".to_string(),
             description: Some("Test rule for reading home directory".to_string()),
             tool: Some("Read".to_string()),
-            tool_regex: None,
-            tool_exclude_regex: None,
             file_path_regex: Some(r"^/home/.*".to_string()),
             file_path_exclude_regex: Some(r"\.\.".to_string()),
-            command_regex: None,
-            command_exclude_regex: None,
-            subagent_type: None,
-            subagent_type_exclude_regex: None,
-            prompt_regex: None,
-            prompt_exclude_regex: None,
+            ..Default::default()
         };
 
-        let rule = compile_rule(&rule_config, "test-section")?;
+        let rule = compile_rule(&rule_config, "test-section", RuleIdScope::Global)?;
         assert_eq!(rule.id, "test-read-rule");
         assert_eq!(rule.section_name, "test-section");
         assert_eq!(rule.tool, Some("Read".to_string()));
@@ -546,4 +2374,1008 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_tool_wildcard() -> Result<()> {
+        let compile = |tool: &str| {
+            let rule_config = RuleConfig {
+                id: "wildcard".to_string(),
+                tool: Some(tool.to_string()),
+                ..Default::default()
+            };
+            compile_rule(&rule_config, "test-section", RuleIdScope::Global)
+        };
+
+        let rule = compile("mcp__github__*")?;
+        assert_eq!(rule.tool, None);
+        assert_eq!(rule.tool_regex.as_ref().unwrap().as_str(), "^mcp__github__.*$");
+        let tools = ["mcp__github__create_issue", "mcp__github__", "mcp__gitlab__create_issue", "x_mcp__github__a"];
+        let selected: Vec<&str> = tools
+            .into_iter()
+            .filter(|tool| crate::matcher::rule_selects_tool(&rule, tool))
+            .collect();
+        assert_eq!(selected, ["mcp__github__create_issue", "mcp__github__"]);
+
+        // Anything else in the pattern is literal, and `*` can sit anywhere
+        let rule = compile("mcp__*__delete_*")?;
+        assert!(crate::matcher::rule_selects_tool(&rule, "mcp__files__delete_file"));
+        assert!(!crate::matcher::rule_selects_tool(&rule, "mcp__files__read_file"));
+        let rule = compile("Notebook.*")?;
+        assert!(crate::matcher::rule_selects_tool(&rule, "Notebook.Edit"));
+        assert!(!crate::matcher::rule_selects_tool(&rule, "NotebookEdit"));
+
+        // Without `*` the name stays an exact match
+        let rule = compile("Bash")?;
+        assert_eq!(rule.tool.as_deref(), Some("Bash"));
+        assert!(rule.tool_regex.is_none());
+        assert!(!crate::matcher::rule_selects_tool(&rule, "BashOutput"));
+        Ok(())
+    }
+
+    fn compile_toml(toml_str: &str) -> Result<CompiledConfig> {
+        let config: Config = toml::from_str(toml_str)?;
+        config.validate()?;
+        Ok(config.compile()?)
+    }
+
+    #[test]
+    fn test_earliest_compiled_rule_wins_ties() -> Result<()> {
+        // Every rule here matches `ls -la`; the winner must follow compiled order:
+        // lowest priority first, then section name, then position within the section
+        let toml_str = r#"
+[zeta]
+priority = 10
+[[zeta.allow]]
+id = "zeta-ls"
+tool = "Bash"
+command_regex = "^ls"
+
+[alpha]
+priority = 10
+[[alpha.allow]]
+id = "alpha-ls"
+tool = "Bash"
+command_regex = "^ls"
+[[alpha.allow]]
+id = "alpha-ls-la"
+tool = "Bash"
+command_regex = "^ls -la$"
+
+[late]
+priority = 50
+[[late.allow]]
+id = "late-any"
+tool_regex = ".*"
+"#;
+        let input = crate::hook_io::HookInput {
+            session_id: "test".to_string(),
+            transcript_path: "/tmp/test".to_string(),
+            cwd: "/tmp".to_string(),
+            hook_event_name: "PreToolUse".to_string(),
+            tool_name: "Bash".to_string(),
+            tool_input: serde_json::json!({"command": "ls -la"}),
+            extra: serde_json::Map::new(),
+            input_root: None,
+            path_style: Default::default(),
+        };
+
+        // Sections live in a HashMap, so recompile repeatedly to catch order leaking through
+        for _ in 0..20 {
+            let compiled = compile_toml(toml_str)?;
+            let ids: Vec<&str> = compiled.allow_rules.iter().map(|r| r.id.as_str()).collect();
+            assert_eq!(ids, ["alpha-ls", "alpha-ls-la", "zeta-ls", "late-any"]);
+            let decision = crate::matcher::check_rules(&compiled.allow_rules, &input).unwrap();
+            assert_eq!(decision.rule_id, "alpha-ls");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_unknown_keys_are_reported() -> Result<()> {
+        let compiled = compile_toml(
+            r#"
+[files]
+prority = 10
+[[files.allow]]
+id = "allow-tmp"
+tool = "Read"
+file_path_rgex = "^/tmp/"
+input_number_min = 1
+"#,
+        )?;
+        assert_eq!(
+            compiled.unknown_keys,
+            vec![
+                "Unknown key 'prority' in section 'files'".to_string(),
+                "Unknown key 'file_path_rgex' in rule 'allow-tmp' (section 'files')".to_string(),
+            ]
+        );
+        // Integer values still reach float fields through the flattened catch-all
+        assert_eq!(compiled.allow_rules[0].input_number_min, Some(1.0));
+        Ok(())
+    }
+
+    #[test]
+    fn test_session_budget_validation() -> Result<()> {
+        let compiled = compile_toml("[session_budget]\nmax_operations_per_session = 500\nreset_after_secs = 3600\n")?;
+        assert_eq!(compiled.session_budget.max_operations_per_session, Some(500));
+        assert_eq!(compiled.session_budget.dir, PathBuf::from("/tmp/claude-hook-budget"));
+
+        let err = compile_toml("[session_budget]\nmax_operations_per_session = 0\nreset_after_secs = 0\n")
+            .err()
+            .unwrap()
+            .to_string();
+        assert!(err.contains("Invalid session_budget.max_operations_per_session 0"), "{}", err);
+        assert!(err.contains("Invalid session_budget.reset_after_secs 0"), "{}", err);
+        Ok(())
+    }
+
+    #[test]
+    fn test_tcp_sink_address() -> Result<()> {
+        let compiled = compile_toml("[logging]\ntcp_sink = \"collector.internal:24224\"\n")?;
+        assert_eq!(compiled.logging.tcp_sink.as_deref(), Some("collector.internal:24224"));
+        assert_eq!(compiled.logging.tcp_sink_timeout_ms, 200);
+        for address in ["collector.internal", ":24224", "collector.internal:port"] {
+            let err = compile_toml(&format!("[logging]\ntcp_sink = \"{}\"\n", address)).err().unwrap().to_string();
+            assert!(err.contains("must be host:port"), "{}", err);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_measure_field_validation() -> Result<()> {
+        let rule = |options: &str| {
+            format!("[security]\n[[security.deny]]\nid = \"deny-blobs\"\ntool = \"Bash\"\n{}\n", options)
+        };
+        let compiled = compile_toml(&rule("measure_field = \"/command\"\nfield_min_entropy = 4.5"))?;
+        assert_eq!(compiled.deny_rules[0].field_min_entropy, Some(4.5));
+        assert_eq!(compiled.deny_rules[0].field_max_length, None);
+
+        let err = compile_toml(&rule("measure_field = \"/command\"")).err().unwrap().to_string();
+        assert!(err.contains("measure_field needs field_min_entropy and/or field_max_length"), "{}", err);
+        let err = compile_toml(&rule("field_max_length = 200")).err().unwrap().to_string();
+        assert!(err.contains("field_min_entropy and field_max_length require measure_field"), "{}", err);
+        let err = compile_toml(&rule("measure_field = \"command\"\nfield_min_entropy = 0"))
+            .err()
+            .unwrap()
+            .to_string();
+        assert!(err.contains("must be a JSON pointer starting with '/'"), "{}", err);
+        assert!(err.contains("field_min_entropy must be a positive number"), "{}", err);
+
+        let allow = "[security]\n[[security.allow]]\nid = \"allow-blobs\"\ntool = \"Bash\"\nmeasure_field = \"/command\"\nfield_max_length = 10\n";
+        let err = compile_toml(allow).err().unwrap().to_string();
+        assert!(err.contains("measure_field"), "{}", err);
+        Ok(())
+    }
+
+    #[test]
+    fn test_allowed_hours_and_timezone() -> Result<()> {
+        assert!(HourWindow::parse("09:00-18:00").is_some());
+        assert!(HourWindow::parse("9-18").is_none());
+        assert!(HourWindow::parse("09:00-09:00").is_none());
+
+        let err = compile_toml("[matching]\ntimezone = \"Mars/Olympus\"\n").err().unwrap().to_string();
+        assert!(err.contains("Invalid matching.timezone 'Mars/Olympus'"), "{}", err);
+
+        let err = compile_toml("[matching]\ninput_root = \"params\"\n").err().unwrap().to_string();
+        assert!(err.contains("Invalid matching.input_root 'params'"), "{}", err);
+        assert!(compile_toml("[matching]\ninput_root = \"/params\"\n").is_ok());
+
+        let err = compile_toml(
+            r#"
+[deploys]
+[[deploys.allow]]
+id = "allow-deploy"
+tool = "Bash"
+allowed_hours = "25:00-18:00"
+"#,
+        )
+        .err()
+        .unwrap()
+        .to_string();
+        assert!(err.contains("Invalid allowed_hours '25:00-18:00' in rule 'allow-deploy'"), "{}", err);
+
+        let noon_utc = DateTime::parse_from_rfc3339("2025-01-01T12:00:00Z")?.with_timezone(&Utc);
+        let matching = |timezone: &str| MatchingConfig {
+            timezone: timezone.to_string(),
+            ..MatchingConfig::default()
+        };
+        assert_eq!(matching("UTC").time_of_day(noon_utc), NaiveTime::from_hms_opt(12, 0, 0).unwrap());
+        assert_eq!(matching("+02:00").time_of_day(noon_utc), NaiveTime::from_hms_opt(14, 0, 0).unwrap());
+        assert_eq!(matching("-05:30").time_of_day(noon_utc), NaiveTime::from_hms_opt(6, 30, 0).unwrap());
+        Ok(())
+    }
+
+    #[test]
+    fn test_self_excluding_rule_warns() -> Result<()> {
+        let compiled = compile_toml(
+            r#"
+[files]
+[[files.allow]]
+id = "same-pattern"
+tool = "Read"
+file_path_regex = "^/tmp/"
+file_path_exclude_regex = "^/tmp/"
+
+[[files.allow]]
+id = "exclude-anything"
+tool = "Bash"
+command_regex = "^ls"
+command_exclude_regex = ".*"
+
+[[files.allow]]
+id = "fine"
+tool = "Bash"
+command_regex = "^ls"
+command_exclude_regex = ";"
+"#,
+        )?;
+        let warnings = compiled.lint_warnings();
+        assert_eq!(warnings.len(), 2);
+        assert!(warnings[0].starts_with("Rule 'same-pattern' in section 'files': file_path_exclude_regex '^/tmp/'"));
+        assert!(warnings[1].starts_with("Rule 'exclude-anything' in section 'files': command_exclude_regex '.*'"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_rule_examples_satisfied() -> Result<()> {
+        let compiled = compile_toml(
+            r#"
+[files]
+[[files.allow]]
+id = "allow-project-reads"
+tool = "Read"
+file_path_regex = "^/home/u/p/"
+file_path_exclude_regex = "\\.env$"
+examples_match = ["/home/u/p/a.rs"]
+examples_no_match = ["/etc/passwd", "/home/u/p/.env"]
+"#,
+        )?;
+        assert!(compiled.example_failures().is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_rule_examples_violated() -> Result<()> {
+        let compiled = compile_toml(
+            r#"
+[security]
+[[security.deny]]
+id = "deny-rm"
+tool = "Bash"
+command_regex = "^rm "
+examples_match = ["rm -rf /", "sudo rm -rf /"]
+examples_no_match = ["rm file.txt"]
+
+[[security.deny]]
+id = "deny-task"
+tool = "Task"
+examples_match = ["anything"]
+"#,
+        )?;
+        assert_eq!(
+            compiled.example_failures(),
+            vec![
+                "Rule 'deny-rm' in section 'security': example 'sudo rm -rf /' should match but doesn't",
+                "Rule 'deny-rm' in section 'security': example 'rm file.txt' should not match but matches command_regex '^rm '",
+                "Rule 'deny-task' in section 'security': has examples but no field regex to test them against",
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_unknown_permission_mode_warns() -> Result<()> {
+        let compiled = compile_toml(
+            r#"
+[security]
+[[security.deny]]
+id = "deny-push-when-bypassing"
+tool = "Bash"
+command_regex = "^git push"
+permission_mode = ["bypassPermissions", "bypass"]
+"#,
+        )?;
+        let warnings = compiled.lint_warnings();
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].starts_with("Rule 'deny-push-when-bypassing' in section 'security': permission_mode 'bypass'"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_tool_fields_config() -> Result<()> {
+        let compiled = compile_toml(
+            r#"
+[tool_fields.NotebookEdit]
+file_path = "notebook_path"
+content = "new_source"
+"#,
+        )?;
+        let fields: Vec<_> = compiled.tool_fields["NotebookEdit"].mapped().collect();
+        assert_eq!(fields, vec![("file_path", "notebook_path"), ("content", "new_source")]);
+
+        let err = compile_toml("[tool_fields.WebFetch]
+").err().unwrap().to_string();
+        assert!(err.contains("tool_fields.WebFetch maps no fields"), "{}", err);
+        let err = compile_toml("[tool_fields.WebFetch]
+url = \"url\"\n").err().unwrap().to_string();
+        assert!(err.contains("unknown field `url`"), "{}", err);
+        Ok(())
+    }
+
+    #[test]
+    fn test_enabled_env_toggles_rule() -> Result<()> {
+        // PATH is always set and non-empty; the other variable is never set
+        let compiled = compile_toml(
+            r#"
+[experimental]
+[[experimental.deny]]
+id = "enabled-by-path"
+tool = "Bash"
+command_regex = "^rm "
+enabled_env = "PATH"
+
+[[experimental.deny]]
+id = "disabled-by-missing-var"
+tool = "Bash"
+command_regex = "^dd "
+enabled_env = "CLAUDE_HOOK_TEST_UNSET_VARIABLE"
+"#,
+        )?;
+
+        let ids: Vec<&str> = compiled.deny_rules.iter().map(|r| r.id.as_str()).collect();
+        assert_eq!(ids, vec!["enabled-by-path"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_stable_hash_is_fnv1a() {
+        // Published FNV-1a 64 test vectors, so stored hashes can't drift
+        assert_eq!(stable_hash(&[""]), "cbf29ce484222325");
+        assert_eq!(stable_hash(&["a"]), "af63dc4c8601ec8c");
+        assert_eq!(stable_hash(&["foobar"]), "85944171f73967e8");
+        assert_ne!(stable_hash(&["ab", "c"]), stable_hash(&["a", "bc"]));
+    }
+
+    #[test]
+    fn test_split_statements_rejected_on_allow_rules() {
+        let err = compile_toml(
+            r#"
+[build]
+[[build.allow]]
+id = "allow-cargo"
+tool = "Bash"
+command_regex = "^cargo "
+split_statements = true
+"#,
+        )
+        .err()
+        .unwrap();
+        assert!(err.to_string().contains("split_statements is only supported on deny rules"));
+    }
+
+    #[test]
+    fn test_split_statements_rejected_with_bash_detectors() {
+        let err = compile_toml(
+            r#"
+[security]
+[[security.deny]]
+id = "deny-hidden-rm"
+tool = "Bash"
+command_regex = "^rm "
+split_statements = true
+detect_obfuscation = true
+"#,
+        )
+        .err()
+        .unwrap();
+        assert!(
+            format!("{:#}", err).contains("split_statements cannot be combined with detect_obfuscation"),
+            "{:#}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_require_llm_concur_validation() {
+        let err = compile_toml(
+            r#"
+[security]
+[[security.deny]]
+id = "deny-rm"
+tool = "Bash"
+command_regex = "^rm "
+require_llm_concur = true
+
+[build]
+[[build.allow]]
+id = "allow-make"
+tool = "Bash"
+command_regex = "^make"
+on_match = "llm"
+require_llm_concur = true
+"#,
+        )
+        .err()
+        .unwrap();
+        let err = format!("{:#}", err);
+        assert!(err.contains("'deny-rm' in section 'security': require_llm_concur is only supported on allow rules"), "{}", err);
+        assert!(err.contains("'allow-make' in section 'build': require_llm_concur cannot be combined with on_match"), "{}", err);
+    }
+
+    #[test]
+    fn test_match_path_args_requires_file_path_regex() {
+        let err = compile_toml(
+            r#"
+[security]
+[[security.deny]]
+id = "deny-shadow-args"
+tool = "Bash"
+command_regex = "^cat "
+match_path_args = true
+"#,
+        )
+        .err()
+        .unwrap();
+        assert!(err.to_string().contains("match_path_args requires file_path_regex"));
+    }
+
+    #[test]
+    fn test_content_preview_options() {
+        let err = compile_toml(
+            r#"
+[security]
+[[security.allow]]
+id = "allow-public-keys"
+tool = "Read"
+content_preview_regex = "ssh-ed25519"
+
+[[security.deny]]
+id = "deny-big-preview"
+tool = "Read"
+content_preview_bytes = 100
+"#,
+        )
+        .err()
+        .unwrap()
+        .to_string();
+        assert!(err.contains("content_preview_regex is only supported on deny rules"), "{}", err);
+        assert!(err.contains("content_preview_bytes requires content_preview_regex"), "{}", err);
+    }
+
+    #[test]
+    fn test_env_value_is_truthy() {
+        assert!(env_value_is_truthy("1"));
+        assert!(env_value_is_truthy("yes"));
+        assert!(!env_value_is_truthy("0"));
+        assert!(!env_value_is_truthy("False"));
+        assert!(!env_value_is_truthy(""));
+    }
+
+    #[test]
+    fn test_validation_reports_all_problems() {
+        let config: Config = toml::from_str(
+            r#"
+[Bad_Section]
+[[Bad_Section.allow]]
+id = "dup"
+tool = "Read"
+
+[good-section]
+[[good-section.deny]]
+id = "dup"
+tool = "Bash"
+command_regex = "(unclosed"
+
+[[good-section.deny]]
+id = "no-tool"
+command_regex = "^rm "
+"#,
+        )
+        .unwrap();
+
+        let validation = config.validate().unwrap_err();
+        let compiled = config.compile().err().unwrap();
+        let problems: Vec<String> = validation.0.into_iter().chain(compiled.0).collect();
+        let report = ConfigErrors(problems).to_string();
+
+        assert!(report.starts_with("Found 4 configuration problem(s):"));
+        assert!(report.contains("Invalid section name 'Bad_Section'"));
+        assert!(report.contains("Duplicate rule ID 'dup'"));
+        assert!(report.contains("Invalid command_regex in rule 'dup' (section 'good-section')"));
+        assert!(report.contains("Rule 'no-tool' in section 'good-section' must have either"));
+    }
+
+    fn write_include_files(files: &[(&str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "hook-include-test-{}-{}",
+            std::process::id(),
+            rand::random::<u64>()
+        ));
+        fs::create_dir_all(&dir).unwrap();
+        for (name, contents) in files {
+            fs::write(dir.join(name), contents).unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn test_secondary_config_keeps_rulesets_apart() -> Result<()> {
+        let dir = write_include_files(&[
+            ("main.toml", "secondary_config = \"secondary.toml\"\n"),
+            (
+                "secondary.toml",
+                "[builtin.debug-tools]\nenabled = false\n[builtin.obfuscation]\nenabled = false\n[builtin.self-protection]\nenabled = false\n\
+                 [policy]\n[[policy.deny]]\nid = \"deny-rm\"\ntool = \"Bash\"\ncommand_regex = \"^rm \"\n",
+            ),
+            ("chained.toml", "secondary_config = \"main.toml\"\n"),
+            ("profiled.toml", "[profiles.ci]\nsecondary_config = \"secondary.toml\"\n"),
+        ]);
+
+        let compiled = Config::load_from_file(&dir.join("main.toml"))?;
+        assert!(compiled.deny_rules.iter().all(|rule| rule.id != "deny-rm"));
+        let secondary = compiled.secondary.unwrap();
+        assert_eq!(secondary.path, dir.join("secondary.toml"));
+        let ids: Vec<&str> = secondary.deny_rules.iter().map(|rule| rule.id.as_str()).collect();
+        assert_eq!(ids, ["deny-rm"]);
+
+        // A secondary can't name a secondary of its own
+        let message = format!("{:#}", Config::load_from_file(&dir.join("chained.toml")).err().unwrap());
+        assert!(message.contains("cannot set secondary_config itself"), "{}", message);
+
+        // Nor can a profile swap it out
+        let message = format!(
+            "{:#}",
+            Config::load_from_file_with_profile(&dir.join("profiled.toml"), Some("ci")).err().unwrap()
+        );
+        assert!(message.contains("cannot override 'secondary_config'"), "{}", message);
+        Ok(())
+    }
+
+    #[test]
+    fn test_include_glob_expands_in_sorted_order() -> Result<()> {
+        let dir = write_include_files(&[(
+            "main.toml",
+            "[includes]\nfiles = [\"rules.d/*.toml\", \"empty.d/*.toml\"]\n",
+        )]);
+        fs::create_dir_all(dir.join("rules.d"))?;
+        let rule = |id: &str| format!("[[{0}.deny]]\nid = \"{0}\"\ntool = \"Bash\"\ncommand_regex = \"^{0} \"\n", id);
+        fs::write(dir.join("rules.d/20-network.toml"), format!("[logging]\nlog_level = \"warn\"\n[curl]\n{}", rule("curl")))?;
+        fs::write(dir.join("rules.d/10-files.toml"), format!("[logging]\nlog_level = \"trace\"\n[rm]\n{}", rule("rm")))?;
+        fs::write(dir.join("rules.d/notes.txt"), "not toml")?;
+
+        let compiled = Config::load_from_file(&dir.join("main.toml"))?;
+        for id in ["rm", "curl"] {
+            assert!(compiled.deny_rules.iter().any(|rule| rule.id == id), "{}", id);
+        }
+        // Matches merge in sorted order, so the first file's settings win
+        assert_eq!(compiled.logging.log_level, "trace");
+
+        assert_eq!(
+            Config::expand_include(&dir, "rules.d/*.toml")?,
+            [dir.join("rules.d/10-files.toml"), dir.join("rules.d/20-network.toml")]
+        );
+        assert!(Config::expand_include(&dir, "empty.d/*.toml")?.is_empty());
+        assert_eq!(Config::expand_include(&dir, "plain.toml")?, [dir.join("plain.toml")]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_circular_include_errors() {
+        let dir = write_include_files(&[
+            ("a.toml", "[includes]\nfiles = [\"b.toml\"]\n"),
+            ("b.toml", "[includes]\nfiles = [\"a.toml\"]\n"),
+        ]);
+
+        let err = Config::load_from_file(&dir.join("a.toml")).err().unwrap();
+        let message = format!("{:#}", err);
+        assert!(message.contains("Include cycle detected"), "{}", message);
+        assert!(message.contains("a.toml -> "), "{}", message);
+        assert!(message.contains("b.toml -> "), "{}", message);
+    }
+
+    #[test]
+    fn test_shared_include_is_not_a_cycle() -> Result<()> {
+        let dir = write_include_files(&[
+            (
+                "main.toml",
+                "[includes]\nfiles = [\"left.toml\", \"right.toml\"]\n[builtin.debug-tools]\nenabled = false\n[builtin.obfuscation]\nenabled = false\n[builtin.self-protection]\nenabled = false\n",
+            ),
+            ("left.toml", "[includes]\nfiles = [\"common.toml\"]\n"),
+            ("right.toml", "[includes]\nfiles = [\"common.toml\"]\n"),
+            (
+                "common.toml",
+                "[shared]\n[[shared.deny]]\nid = \"deny-rm\"\ntool = \"Bash\"\ncommand_regex = \"^rm \"\n",
+            ),
+        ]);
+
+        let compiled = Config::load_from_file(&dir.join("main.toml"))?;
+        assert_eq!(compiled.deny_rules.len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_include_depth_limit() {
+        let files: Vec<(String, String)> = (0..=MAX_INCLUDE_DEPTH)
+            .map(|i| (format!("{}.toml", i), format!("[includes]\nfiles = [\"{}.toml\"]\n", i + 1)))
+            .chain(std::iter::once((format!("{}.toml", MAX_INCLUDE_DEPTH + 1), String::new())))
+            .collect();
+        let refs: Vec<(&str, &str)> = files.iter().map(|(n, c)| (n.as_str(), c.as_str())).collect();
+        let dir = write_include_files(&refs);
+
+        let err = Config::load_from_file(&dir.join("0.toml")).err().unwrap();
+        assert!(format!("{:#}", err).contains("Include depth exceeds"));
+    }
+
+    #[test]
+    fn test_include_size_limit() -> Result<()> {
+        // Each include is about 2KB of comments; two fit under 5000 bytes, three don't
+        let padding = format!("# {}\n", "x".repeat(2000));
+        let dir = write_include_files(&[
+            ("main.toml", "[includes]\nfiles = [\"a.toml\", \"b.toml\"]\nmax_total_bytes = 5000\n"),
+            ("a.toml", &padding),
+            ("b.toml", &padding),
+            ("c.toml", &padding),
+        ]);
+        Config::load_from_file(&dir.join("main.toml"))?;
+
+        fs::write(
+            dir.join("main.toml"),
+            "[includes]\nfiles = [\"a.toml\", \"b.toml\", \"c.toml\"]\nmax_total_bytes = 5000\n",
+        )?;
+        let err = format!("{:#}", Config::load_from_file(&dir.join("main.toml")).err().unwrap());
+        assert!(err.contains("Config size limit exceeded at"), "{}", err);
+        assert!(err.contains("c.toml") && err.contains("(5000 bytes)"), "{}", err);
+
+        // The default cap leaves ordinary configs alone
+        fs::write(dir.join("main.toml"), "[includes]\nfiles = [\"a.toml\", \"b.toml\", \"c.toml\"]\n")?;
+        Config::load_from_file(&dir.join("main.toml"))?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_incremental_reload_matches_full_reload() -> Result<()> {
+        let deny_read = |id: &str, path_regex: &str| {
+            format!(
+                "[files]\n[[files.deny]]\nid = \"{}\"\ntool = \"Read\"\nfile_path_regex = \"{}\"\n",
+                id, path_regex
+            )
+        };
+        let dir = write_include_files(&[
+            ("main.toml", "[includes]\nfiles = [\"git.toml\", \"files.toml\"]\n"),
+            (
+                "git.toml",
+                "[git]\n[[git.allow]]\nid = \"allow-status\"\ntool = \"Bash\"\ncommand_regex = \"^git status\"\n",
+            ),
+            ("files.toml", &deny_read("deny-env", "env$")),
+        ]);
+        let main = dir.join("main.toml");
+        let set_modified = |path: &Path, time: SystemTime| -> Result<()> {
+            fs::File::options().write(true).open(path)?.set_modified(time)?;
+            Ok(())
+        };
+        let rule_ids = |compiled: &CompiledConfig| -> Vec<String> {
+            compiled.deny_rules.iter().chain(&compiled.allow_rules).map(|rule| rule.id.clone()).collect()
+        };
+
+        let mut cache = IncludeCache::default();
+        let first = Config::load_cached(&main, None, &mut cache)?;
+        assert_eq!(cache.files.len(), 3);
+
+        let files = dir.join("files.toml");
+        fs::write(&files, deny_read("deny-keys", "pem$"))?;
+        set_modified(&files, SystemTime::now() + std::time::Duration::from_secs(5))?;
+        let incremental = Config::load_cached(&main, None, &mut cache)?;
+        let full = Config::load_from_file(&main)?;
+        assert_eq!(incremental.config_hash, full.config_hash);
+        assert_ne!(incremental.config_hash, first.config_hash);
+        assert_eq!(rule_ids(&incremental), rule_ids(&full));
+        assert!(rule_ids(&incremental).contains(&"deny-keys".to_string()));
+
+        // Unchanged files really are reused: an edit that keeps the size and mtime goes unseen
+        let git = dir.join("git.toml");
+        let modified = fs::metadata(&git)?.modified()?;
+        fs::write(&git, fs::read_to_string(&git)?.replace("allow-status", "allow-statuz"))?;
+        set_modified(&git, modified)?;
+        let reused = Config::load_cached(&main, None, &mut cache)?;
+        assert_eq!(reused.config_hash, incremental.config_hash);
+        assert!(rule_ids(&Config::load_from_file(&main)?).contains(&"allow-statuz".to_string()));
+        Ok(())
+    }
+
+    const PROFILE_CONFIG: &str = r#"
+[logging]
+log_level = "info"
+
+[build]
+[[build.allow]]
+id = "allow-cargo"
+tool = "Bash"
+command_regex = "^cargo "
+
+[risky]
+enabled = false
+[[risky.allow]]
+id = "allow-curl"
+tool = "Bash"
+command_regex = "^curl "
+
+[profiles.dev]
+logging = { log_level = "debug" }
+risky = { enabled = true }
+
+[profiles.prod]
+build = { enabled = false }
+"#;
+
+    #[test]
+    fn test_profiles_select_sections_and_settings() -> Result<()> {
+        let dir = write_include_files(&[("hook.toml", PROFILE_CONFIG)]);
+        let path = dir.join("hook.toml");
+        let allow_ids = |compiled: &CompiledConfig| -> Vec<String> {
+            compiled.allow_rules.iter().map(|r| r.id.clone()).collect()
+        };
+
+        let base = Config::load_from_file(&path)?;
+        assert_eq!(allow_ids(&base), vec!["allow-cargo"]);
+        assert_eq!(base.logging.log_level, "info");
+
+        let dev = Config::load_from_file_with_profile(&path, Some("dev"))?;
+        assert_eq!(allow_ids(&dev), vec!["allow-cargo", "allow-curl"]);
+        assert_eq!(dev.logging.log_level, "debug");
+
+        let prod = Config::load_from_file_with_profile(&path, Some("prod"))?;
+        assert!(allow_ids(&prod).is_empty());
+        assert_eq!(prod.logging.log_level, "info");
+        Ok(())
+    }
+
+    #[test]
+    fn test_profile_errors() {
+        let config = format!(
+            "{}\n[profiles.typo]\nbuidl = {{ enabled = false }}\n",
+            PROFILE_CONFIG
+        );
+        let dir = write_include_files(&[("hook.toml", config.as_str())]);
+        let path = dir.join("hook.toml");
+
+        let err = Config::load_from_file_with_profile(&path, Some("staging")).err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "Unknown profile 'staging'. Available profiles: dev, prod, typo"
+        );
+
+        let err = Config::load_from_file_with_profile(&path, Some("typo")).err().unwrap();
+        assert!(err.to_string().contains("Profile 'typo' references unknown section 'buidl'"));
+    }
+
+    #[test]
+    fn test_flat_config_asks_for_migration() {
+        let dir = write_include_files(&[("flat.toml", "[[allow]]\ntool = \"Read\"\n")]);
+        let err = Config::load_from_file(&dir.join("flat.toml")).err().unwrap();
+        assert!(err.to_string().contains("uses schema version 1"));
+        assert!(err.to_string().contains("migrate --input"));
+    }
+
+    #[test]
+    fn test_bypass_env_defaults_from_rule_id() -> Result<()> {
+        let compiled = compile_toml(
+            r#"
+[security]
+[[security.deny]]
+id = "deny-force-push"
+tool = "Bash"
+command_regex = "push --force"
+bypass_value = "i-know"
+"#,
+        )?;
+        assert_eq!(
+            compiled.deny_rules[0].bypass,
+            Some(("HOOK_CONFIRM_DENY_FORCE_PUSH".to_string(), "i-know".to_string()))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_rule_id_scope_global_requires_unique_ids() {
+        let err = compile_toml(
+            r#"
+[git]
+[[git.deny]]
+id = "deny-force"
+tool = "Bash"
+command_regex = "push --force"
+
+[docker]
+[[docker.deny]]
+id = "deny-force"
+tool = "Bash"
+command_regex = "rm --force"
+"#,
+        )
+        .err()
+        .expect("duplicate ids across sections should fail in global scope");
+        assert!(err.to_string().contains("unique across all sections"), "{}", err);
+    }
+
+    #[test]
+    fn test_rule_id_scope_section_qualifies_ids() -> Result<()> {
+        let compiled = compile_toml(
+            r#"
+rule_id_scope = "section"
+
+[git]
+[[git.deny]]
+id = "deny-force"
+tool = "Bash"
+command_regex = "push --force"
+bypass_value = "i-know"
+
+[docker]
+[[docker.deny]]
+id = "deny-force"
+tool = "Bash"
+command_regex = "rm --force"
+"#,
+        )?;
+        let ids: Vec<&str> = compiled.deny_rules.iter().map(|rule| rule.id.as_str()).collect();
+        assert_eq!(ids, ["docker/deny-force", "git/deny-force"]);
+        let git = compiled.deny_rules.iter().find(|rule| rule.id == "git/deny-force").unwrap();
+        assert_eq!(
+            git.bypass,
+            Some(("HOOK_CONFIRM_GIT_DENY_FORCE".to_string(), "i-know".to_string()))
+        );
+
+        let err = compile_toml(
+            r#"
+rule_id_scope = "section"
+
+[git]
+[[git.deny]]
+id = "deny-force"
+tool = "Bash"
+command_regex = "push --force"
+
+[[git.allow]]
+id = "deny-force"
+tool = "Bash"
+command_regex = "^git status"
+"#,
+        )
+        .err()
+        .expect("duplicate ids within a section should still fail");
+        assert!(err.to_string().contains("unique within a section"), "{}", err);
+        Ok(())
+    }
+
+    #[test]
+    fn test_require_tls_rejects_http_endpoint() {
+        let llm = |endpoint: &str, allow_localhost_http: bool| LlmFallbackConfig {
+            enabled: true,
+            endpoint: Some(endpoint.to_string()),
+            model: Some("test-model".to_string()),
+            require_tls: true,
+            allow_localhost_http,
+            ..LlmFallbackConfig::default()
+        };
+
+        let err = llm("http://llm.example.com/v1", true).validate().unwrap_err();
+        assert!(err.to_string().contains("require_tls"), "{}", err);
+        assert!(llm("https://llm.example.com/v1", false).validate().is_ok());
+
+        // Loopback hosts are only exempt when allow_localhost_http is set
+        assert!(llm("http://localhost:11434/v1", false).validate().is_err());
+        for endpoint in ["http://localhost:11434/v1", "http://127.0.0.1:8080", "http://[::1]:11434/v1"] {
+            assert!(llm(endpoint, true).validate().is_ok(), "{}", endpoint);
+        }
+        assert!(llm("http://localhost.example.com/v1", true).validate().is_err());
+        assert!(llm("http://localhost@llm.example.com/v1", true).validate().is_err());
+
+        let mut plain = llm("http://llm.example.com/v1", false);
+        plain.require_tls = false;
+        assert!(plain.validate().is_ok());
+    }
+
+    #[test]
+    fn test_llm_timeout_and_temperature_bounds() -> Result<()> {
+        let llm = LlmFallbackConfig {
+            enabled: true,
+            endpoint: Some("https://llm.example.com/v1".to_string()),
+            model: Some("test-model".to_string()),
+            timeout_secs: 10,
+            ..LlmFallbackConfig::default()
+        };
+        assert!(llm.validate().is_ok());
+
+        let zero_timeout = LlmFallbackConfig { timeout_secs: 0, ..llm.clone() };
+        let err = zero_timeout.validate().unwrap_err().to_string();
+        assert!(err.contains("Invalid llm_fallback.timeout_secs 0"), "{}", err);
+        for temperature in [-0.1, 2.5, f32::NAN] {
+            let hot = LlmFallbackConfig { temperature, ..llm.clone() };
+            let err = hot.validate().unwrap_err().to_string();
+            assert!(err.contains("Invalid llm_fallback.temperature"), "{}", err);
+        }
+        // Disabled fallbacks aren't checked
+        assert!(LlmFallbackConfig { enabled: false, ..zero_timeout }.validate().is_ok());
+
+        // Negative retries can't even be parsed
+        let err = compile_toml("[llm_fallback]\nmax_retries = -1\n").err().unwrap();
+        assert!(format!("{:#}", err).contains("max_retries"), "{:#}", err);
+        Ok(())
+    }
+
+    #[test]
+    fn test_timeout_budget_warning() -> Result<()> {
+        let llm = |timeout_secs: u64, matching_ms: u64| {
+            compile_toml(&format!(
+                "[matching]\ntimeout_ms = {}\n[llm_fallback]\nenabled = true\nendpoint = \"https://llm.example.com/v1\"\n\
+                 model = \"m\"\ntimeout_secs = {}\n",
+                matching_ms, timeout_secs
+            ))
+        };
+        assert!(llm(30, 1000)?.lint_warnings().is_empty());
+
+        let warnings = llm(60, 1000)?.lint_warnings();
+        assert_eq!(warnings.len(), 1);
+        assert!(
+            warnings[0].starts_with("matching.timeout_ms (1000) plus llm_fallback.timeout_secs (60s) reaches"),
+            "{}",
+            warnings[0]
+        );
+        // The matching deadline counts toward the same budget
+        assert_eq!(llm(55, 5000)?.lint_warnings().len(), 1);
+        Ok(())
+    }
+
+    fn llm_with_key(api_key: Option<&str>, api_key_file: Option<&str>) -> LlmFallbackConfig {
+        LlmFallbackConfig {
+            enabled: true,
+            api_key: api_key.map(str::to_string),
+            api_key_file: api_key_file.map(PathBuf::from),
+            ..LlmFallbackConfig::default()
+        }
+    }
+
+    #[test]
+    fn test_api_key_env_reference() -> Result<()> {
+        let mut llm = llm_with_key(Some("env:PATH"), None);
+        llm.resolve_api_key(Path::new("."))?;
+        assert_eq!(llm.api_key, Some(std::env::var("PATH")?));
+
+        let mut llm = llm_with_key(Some("env:CLAUDE_HOOK_TEST_UNSET_VARIABLE"), None);
+        let err = llm.resolve_api_key(Path::new(".")).unwrap_err();
+        assert!(err.to_string().contains("'CLAUDE_HOOK_TEST_UNSET_VARIABLE', which is not set"));
+
+        // Disabled fallback never needs the key
+        let mut llm = llm_with_key(Some("env:CLAUDE_HOOK_TEST_UNSET_VARIABLE"), None);
+        llm.enabled = false;
+        llm.resolve_api_key(Path::new("."))?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_api_key_file_resolution() -> Result<()> {
+        let dir = write_include_files(&[("key.txt", "sk-from-file\n"), ("empty.txt", "")]);
+
+        let mut llm = llm_with_key(None, Some("key.txt"));
+        llm.resolve_api_key(&dir)?;
+        assert_eq!(llm.api_key.as_deref(), Some("sk-from-file"));
+
+        let path = dir.join("key.txt");
+        let mut llm = llm_with_key(Some(&format!("file:{}", path.display())), None);
+        llm.resolve_api_key(Path::new("/"))?;
+        assert_eq!(llm.api_key.as_deref(), Some("sk-from-file"));
+
+        let mut llm = llm_with_key(Some("sk-literal"), None);
+        llm.resolve_api_key(&dir)?;
+        assert_eq!(llm.api_key.as_deref(), Some("sk-literal"));
+
+        let missing = llm_with_key(None, Some("missing.txt")).resolve_api_key(&dir).unwrap_err();
+        assert!(missing.to_string().contains("Failed to read llm_fallback API key file"));
+        let empty = llm_with_key(None, Some("empty.txt")).resolve_api_key(&dir).unwrap_err();
+        assert!(empty.to_string().contains("API key file is empty"));
+        let both = llm_with_key(Some("sk"), Some("key.txt")).resolve_api_key(&dir).unwrap_err();
+        assert!(both.to_string().contains("not both"));
+        Ok(())
+    }
 }
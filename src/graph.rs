@@ -0,0 +1,310 @@
+#![forbid(unsafe_code)]
+#![warn(clippy::all)]
+
+use crate::config::{CompiledConfig, OnMatch, QueryDecision, Rule};
+use std::fmt::Write;
+
+/// Diagram syntax for `graph`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum GraphFormat {
+    Mermaid,
+    Dot,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Shape {
+    Terminal,
+    Decision,
+    Outcome,
+}
+
+struct Node {
+    id: String,
+    label: Vec<String>,
+    shape: Shape,
+}
+
+struct Edge {
+    from: String,
+    to: String,
+    label: &'static str,
+}
+
+/// Evaluation order of a compiled config as nodes and edges, independent of output syntax
+#[derive(Default)]
+struct Flowchart {
+    nodes: Vec<Node>,
+    edges: Vec<Edge>,
+}
+
+impl Flowchart {
+    fn node(&mut self, id: &str, label: Vec<String>, shape: Shape) {
+        self.nodes.push(Node { id: id.to_string(), label, shape });
+    }
+
+    fn edge(&mut self, from: &str, to: &str, label: &'static str) {
+        self.edges.push(Edge { from: from.to_string(), to: to.to_string(), label });
+    }
+}
+
+/// The patterns a rule matches on, one `name = value` line each
+fn matcher_lines(rule: &Rule) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut push = |name: &str, value: Option<String>| {
+        if let Some(value) = value {
+            lines.push(format!("{} = {}", name, value));
+        }
+    };
+
+    let regex = |r: &Option<regex::Regex>| r.as_ref().map(|r| r.as_str().to_string());
+    push("tool", rule.tool.clone());
+    push("tool_regex", regex(&rule.tool_regex));
+    push("tool_exclude_regex", regex(&rule.tool_exclude_regex));
+    push("mcp_server", rule.mcp_server.clone());
+    push("file_path_regex", regex(&rule.file_path_regex));
+    push("file_path_exclude_regex", regex(&rule.file_path_exclude_regex));
+    push("command_regex", regex(&rule.command_regex));
+    push("command_exclude_regex", regex(&rule.command_exclude_regex));
+    push("subagent_type", rule.subagent_type.clone());
+    push("subagent_type_exclude_regex", regex(&rule.subagent_type_exclude_regex));
+    push("prompt_regex", regex(&rule.prompt_regex));
+    push("prompt_exclude_regex", regex(&rule.prompt_exclude_regex));
+    push("content_regex", regex(&rule.content_regex));
+    push("content_exclude_regex", regex(&rule.content_exclude_regex));
+    push("raw_input_regex", regex(&rule.raw_input_regex));
+    push("raw_input_exclude_regex", regex(&rule.raw_input_exclude_regex));
+    push("bash_id_regex", regex(&rule.bash_id_regex));
+    push("bash_id_exclude_regex", regex(&rule.bash_id_exclude_regex));
+    push("description_regex", regex(&rule.description_regex));
+    push("description_exclude_regex", regex(&rule.description_exclude_regex));
+    for (field, pattern) in &rule.context_regex {
+        push(&format!("context_regex.{}", field), Some(pattern.as_str().to_string()));
+    }
+    push("within_project", rule.within_project.map(|w| w.to_string()));
+    push("allowed_hours", rule.allowed_hours.map(|w| w.to_string()));
+    if !rule.permission_mode.is_empty() {
+        push("permission_mode", Some(rule.permission_mode.join(", ")));
+    }
+    push("max_shell_metachars", rule.max_shell_metachars.map(|n| n.to_string()));
+    push(
+        "content_preview_regex",
+        rule.content_preview_regex.as_ref().map(|r| r.as_str().to_string()),
+    );
+    push("redirect_target_regex", regex(&rule.redirect_target_regex));
+    push("input_number", rule.input_number.clone());
+    push("measure_field", rule.measure_field.clone());
+    push("enforce", rule.audit_only.then(|| "false".to_string()));
+    let flags = [
+        ("split_statements", rule.split_statements),
+        ("match_path_args", rule.match_path_args),
+        ("deny_relative", rule.deny_relative),
+        ("detect_obfuscation", rule.detect_obfuscation),
+        ("requires_elevation", rule.requires_elevation),
+        ("sensitive_paths", rule.sensitive_paths),
+        ("deny_broad_glob", rule.deny_broad_glob),
+        ("require_absolute", rule.require_absolute),
+        ("path_must_not_be_symlink", rule.path_must_not_be_symlink),
+        ("path_must_exist", rule.path_must_exist),
+        ("path_must_not_exist", rule.path_must_not_exist),
+    ];
+    for (name, set) in flags {
+        if set {
+            push(name, Some("true".to_string()));
+        }
+    }
+    lines
+}
+
+fn rule_label(rule: &Rule) -> Vec<String> {
+    let mut label = vec![format!("{} [{}]", rule.id, rule.section_name)];
+    label.extend(matcher_lines(rule));
+    label
+}
+
+/// Leaving a rule node means it didn't match; leaving the start node needs no label
+fn miss_label(from: &str) -> &'static str {
+    if from == "start" { "" } else { "no match" }
+}
+
+/// Chain one list of rules: each match leads to `on_match`, each miss to the next rule
+/// Returns the id of the node a miss on the last rule falls through from
+fn add_rules(chart: &mut Flowchart, rules: &[Rule], prefix: &str, entry: &str, on_match: &str) -> String {
+    let mut previous = entry.to_string();
+    for (idx, rule) in rules.iter().enumerate() {
+        let id = format!("{}_{}", prefix, idx);
+        chart.node(&id, rule_label(rule), Shape::Decision);
+        chart.edge(&previous, &id, miss_label(&previous));
+
+        let asks_llm = rule.on_match == OnMatch::Llm || rule.require_llm_concur;
+        let target = if asks_llm { "escalate" } else { on_match };
+        chart.edge(&id, target, "match");
+        previous = id;
+    }
+    previous
+}
+
+fn build(compiled: &CompiledConfig) -> Flowchart {
+    let mut chart = Flowchart::default();
+    chart.node("start", vec!["Tool use request".to_string()], Shape::Terminal);
+    chart.node("deny", vec!["Deny".to_string()], Shape::Outcome);
+    chart.node("allow", vec!["Allow".to_string()], Shape::Outcome);
+    chart.node("passthrough", vec!["Passthrough to user".to_string()], Shape::Outcome);
+
+    let escalates = compiled
+        .deny_rules
+        .iter()
+        .chain(&compiled.allow_rules)
+        .any(|rule| rule.on_match == OnMatch::Llm || rule.require_llm_concur);
+    if escalates {
+        chart.node("escalate", vec!["LLM second opinion".to_string()], Shape::Outcome);
+    }
+
+    let last_deny = add_rules(&mut chart, &compiled.deny_rules, "deny_rule", "start", "deny");
+    let last_allow = add_rules(&mut chart, &compiled.allow_rules, "allow_rule", &last_deny, "allow");
+    if compiled.llm_fallback.enabled {
+        chart.node("llm", vec!["LLM fallback".to_string()], Shape::Decision);
+        chart.edge(&last_allow, "llm", miss_label(&last_allow));
+        chart.edge("llm", "allow", "ALLOW");
+        let query_target = match compiled.llm_fallback.query_decision {
+            QueryDecision::Passthrough => "passthrough",
+            QueryDecision::Deny => "deny",
+            QueryDecision::Ask => {
+                chart.node("ask", vec!["Ask user".to_string()], Shape::Outcome);
+                "ask"
+            }
+        };
+        chart.edge("llm", query_target, "QUERY");
+    } else {
+        chart.edge(&last_allow, "passthrough", miss_label(&last_allow));
+    }
+    chart
+}
+
+fn escape_mermaid(s: &str) -> String {
+    s.replace('"', "#quot;").replace('<', "#lt;").replace('>', "#gt;")
+}
+
+fn escape_dot(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn render_mermaid(chart: &Flowchart) -> String {
+    let mut out = String::from("flowchart TD\n");
+    for node in &chart.nodes {
+        let label = node.label.iter().map(|l| escape_mermaid(l)).collect::<Vec<_>>().join("<br/>");
+        let (open, close) = match node.shape {
+            Shape::Terminal => ("([", "])"),
+            Shape::Decision => ("{", "}"),
+            Shape::Outcome => ("[", "]"),
+        };
+        writeln!(out, "    {}{}\"{}\"{}", node.id, open, label, close).unwrap();
+    }
+    for edge in &chart.edges {
+        if edge.label.is_empty() {
+            writeln!(out, "    {} --> {}", edge.from, edge.to).unwrap();
+        } else {
+            writeln!(out, "    {} -->|{}| {}", edge.from, edge.label, edge.to).unwrap();
+        }
+    }
+    out
+}
+
+fn render_dot(chart: &Flowchart) -> String {
+    let mut out = String::from("digraph rules {\n    node [fontname=\"monospace\"];\n");
+    for node in &chart.nodes {
+        let label = node.label.iter().map(|l| escape_dot(l)).collect::<Vec<_>>().join("\\n");
+        let shape = match node.shape {
+            Shape::Terminal => "oval",
+            Shape::Decision => "diamond",
+            Shape::Outcome => "box",
+        };
+        writeln!(out, "    {} [shape={}, label=\"{}\"];", node.id, shape, label).unwrap();
+    }
+    for edge in &chart.edges {
+        writeln!(out, "    {} -> {} [label=\"{}\"];", edge.from, edge.to, edge.label).unwrap();
+    }
+    out.push_str("}\n");
+    out
+}
+
+/// Diagram of the evaluation order: deny rules, allow rules, then the LLM fallback or passthrough
+pub fn render(compiled: &CompiledConfig, format: GraphFormat) -> String {
+    let chart = build(compiled);
+    match format {
+        GraphFormat::Mermaid => render_mermaid(&chart),
+        GraphFormat::Dot => render_dot(&chart),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::LlmFallbackConfig;
+    use pretty_assertions::assert_eq;
+    use regex::Regex;
+
+    fn compiled(llm_enabled: bool) -> CompiledConfig {
+        CompiledConfig {
+            logging: Default::default(),
+            llm_fallback: LlmFallbackConfig {
+                enabled: llm_enabled,
+                ..Default::default()
+            },
+            output: Default::default(),
+            cache: Default::default(),
+            session_approvals: Default::default(),
+            session_budget: Default::default(),
+            matching: Default::default(),
+            tool_fields: Default::default(),
+            deny_rules: vec![Rule {
+                id: "deny-rm".to_string(),
+                section_name: "security".to_string(),
+                tool: Some("Bash".to_string()),
+                command_regex: Some(Regex::new("^rm \"x\"").unwrap()),
+                ..Default::default()
+            }],
+            allow_rules: vec![Rule {
+                id: "allow-read".to_string(),
+                section_name: "files".to_string(),
+                tool: Some("Read".to_string()),
+                on_match: OnMatch::Llm,
+                ..Default::default()
+            }],
+            unknown_keys: Vec::new(),
+            secondary: None,
+            config_hash: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_render_mermaid() {
+        let expected = r#"flowchart TD
+    start(["Tool use request"])
+    deny["Deny"]
+    allow["Allow"]
+    passthrough["Passthrough to user"]
+    escalate["LLM second opinion"]
+    deny_rule_0{"deny-rm [security]<br/>tool = Bash<br/>command_regex = ^rm #quot;x#quot;"}
+    allow_rule_0{"allow-read [files]<br/>tool = Read"}
+    llm{"LLM fallback"}
+    start --> deny_rule_0
+    deny_rule_0 -->|match| deny
+    deny_rule_0 -->|no match| allow_rule_0
+    allow_rule_0 -->|match| escalate
+    allow_rule_0 -->|no match| llm
+    llm -->|ALLOW| allow
+    llm -->|QUERY| passthrough
+"#;
+        assert_eq!(render(&compiled(true), GraphFormat::Mermaid), expected);
+    }
+
+    #[test]
+    fn test_render_dot_without_llm() {
+        let dot = render(&compiled(false), GraphFormat::Dot);
+        assert!(dot.starts_with("digraph rules {\n"));
+        assert!(dot.contains(r#"deny_rule_0 [shape=diamond, label="deny-rm [security]\ntool = Bash\ncommand_regex = ^rm \"x\""];"#));
+        assert!(dot.contains(r#"allow_rule_0 -> passthrough [label="no match"];"#));
+        assert!(!dot.contains("llm"));
+        assert!(dot.ends_with("}\n"));
+    }
+}
@@ -0,0 +1,164 @@
+#![forbid(unsafe_code)]
+#![warn(clippy::all)]
+
+use crate::config::LlmFallbackConfig;
+use crate::hook_io::{session_file_stem, HookInput};
+use anyhow::{Context, Result};
+use log::warn;
+use nix::fcntl::{Flock, FlockArg};
+use serde::{Deserialize, Serialize};
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+// ========== SESSION DECISION HISTORY ==========
+// Purpose: Let the LLM see what was decided earlier in the same session
+// Location: /tmp/claude-hook-history/<session_id>.jsonl (or configured directory)
+
+/// Longest tool input summary kept per entry, so long commands don't bloat the prompt
+const MAX_SUMMARY_CHARS: usize = 200;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub decision: String,
+    pub tool_name: String,
+    pub summary: String,
+}
+
+impl HistoryEntry {
+    pub fn new(input: &HookInput, decision: &str) -> Self {
+        // Bash commands are the common case and read better without the JSON wrapper
+        let summary = input
+            .extract_field("command")
+            .unwrap_or_else(|| input.tool_input.to_string());
+        HistoryEntry {
+            decision: decision.to_string(),
+            tool_name: input.tool_name.clone(),
+            summary: truncate(&summary, MAX_SUMMARY_CHARS),
+        }
+    }
+}
+
+fn truncate(s: &str, max_chars: usize) -> String {
+    match s.char_indices().nth(max_chars) {
+        Some((idx, _)) => format!("{}...", &s[..idx]),
+        None => s.to_string(),
+    }
+}
+
+fn history_file(dir: &Path, session_id: &str) -> PathBuf {
+    dir.join(format!("{}.jsonl", session_file_stem(session_id)))
+}
+
+/// The last `config.include_decision_history` decisions of this session, oldest first
+/// A missing or unreadable file just means no history yet
+pub fn recent(config: &LlmFallbackConfig, session_id: &str) -> Vec<HistoryEntry> {
+    if config.include_decision_history == 0 {
+        return Vec::new();
+    }
+    let path = history_file(&config.decision_history_dir, session_id);
+    let Ok(contents) = std::fs::read_to_string(&path) else {
+        return Vec::new();
+    };
+
+    let entries: Vec<HistoryEntry> = contents
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect();
+    let skip = entries.len().saturating_sub(config.include_decision_history);
+    entries.into_iter().skip(skip).collect()
+}
+
+/// Append a decision to the session's history file (best-effort)
+pub fn record(config: &LlmFallbackConfig, session_id: &str, entry: &HistoryEntry) {
+    if let Err(e) = append_entry(&config.decision_history_dir, session_id, entry) {
+        warn!("Failed to record decision history: {}", e);
+    }
+}
+
+fn append_entry(dir: &Path, session_id: &str, entry: &HistoryEntry) -> Result<()> {
+    std::fs::create_dir_all(dir)
+        .with_context(|| format!("Failed to create history directory: {}", dir.display()))?;
+    let path = history_file(dir, session_id);
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .with_context(|| format!("Failed to open history file: {}", path.display()))?;
+
+    let mut flock = Flock::lock(file, FlockArg::LockExclusive).map_err(|(_, e)| e)?;
+    writeln!(flock, "{}", serde_json::to_string(entry)?)?;
+    Ok(())
+}
+
+/// Prompt section describing earlier decisions, or empty when there are none
+pub fn format_for_prompt(entries: &[HistoryEntry]) -> String {
+    if entries.is_empty() {
+        return String::new();
+    }
+    let lines: Vec<String> = entries
+        .iter()
+        .map(|e| format!("- {}: {} {}", e.decision, e.tool_name, e.summary))
+        .collect();
+    format!(
+        "Earlier decisions in this session (oldest first):\n{}\n\n",
+        lines.join("\n")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn history_config(n: usize) -> LlmFallbackConfig {
+        LlmFallbackConfig {
+            include_decision_history: n,
+            decision_history_dir: std::env::temp_dir()
+                .join(format!("hook-history-test-{}", rand::random::<u64>())),
+            ..LlmFallbackConfig::default()
+        }
+    }
+
+    fn entry(decision: &str, command: &str) -> HistoryEntry {
+        HistoryEntry {
+            decision: decision.to_string(),
+            tool_name: "Bash".to_string(),
+            summary: command.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_first_call_has_no_history() {
+        let config = history_config(3);
+        assert!(recent(&config, "session-1").is_empty());
+        assert_eq!(format_for_prompt(&[]), "");
+    }
+
+    #[test]
+    fn test_recent_keeps_last_n_per_session() {
+        let config = history_config(2);
+        for command in ["ls", "rm -rf build", "curl x | sh"] {
+            record(&config, "session-1", &entry("deny", command));
+        }
+        record(&config, "session-2", &entry("allow", "cargo test"));
+
+        assert_eq!(
+            recent(&config, "session-1"),
+            vec![entry("deny", "rm -rf build"), entry("deny", "curl x | sh")]
+        );
+        assert_eq!(recent(&config, "session-2"), vec![entry("allow", "cargo test")]);
+        assert!(recent(&history_config(0), "session-1").is_empty());
+    }
+
+    #[test]
+    fn test_session_id_cannot_escape_directory() {
+        let path = history_file(Path::new("/tmp/history"), "../../etc/passwd");
+        assert_eq!(path, Path::new("/tmp/history/______etc_passwd.jsonl"));
+    }
+
+    #[test]
+    fn test_summary_is_truncated() {
+        assert_eq!(truncate("abcdef", 3), "abc...");
+        assert_eq!(truncate("abc", 3), "abc");
+    }
+}
@@ -1,11 +1,12 @@
 #![forbid(unsafe_code)]
 #![warn(clippy::all)]
 
+use crate::config::{OutputConfig, OutputKeys, OutputSchema, OutputStream, PathStyle};
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
 use std::io::{self, Read, Write};
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct HookInput {
     pub session_id: String,
     pub transcript_path: String,
@@ -13,6 +14,15 @@ pub struct HookInput {
     pub hook_event_name: String,
     pub tool_name: String,
     pub tool_input: serde_json::Value,
+    /// Any other top-level fields, so new Claude Code fields are matchable without code changes
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+    /// JSON pointer to an object in tool_input that wraps the real arguments (`matching.input_root`)
+    #[serde(skip)]
+    pub input_root: Option<String>,
+    /// How path fields and cwd are normalized before matching (`matching.path_style`)
+    #[serde(skip)]
+    pub path_style: PathStyle,
 }
 
 #[derive(Debug, Serialize)]
@@ -21,6 +31,17 @@ pub struct HookOutput {
     pub hook_specific_output: HookSpecificOutput,
     #[serde(rename = "suppressOutput")]
     pub suppress_output: bool,
+    /// Machine-readable provenance of the decision, emitted when `output.reason_detail` is set
+    #[serde(rename = "reasonDetail", skip_serializing_if = "Option::is_none")]
+    pub reason_detail: Option<ReasonDetail>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ReasonDetail {
+    pub source: String,
+    pub rule_id: Option<String>,
+    pub matched_pattern: Option<String>,
+    pub risk_level: String,
 }
 
 #[derive(Debug, Serialize)]
@@ -46,12 +67,79 @@ impl HookInput {
         Ok(input)
     }
 
+    /// Claude Code's permission mode (e.g. "plan", "bypassPermissions"), when the input has one
+    pub fn permission_mode(&self) -> Option<&str> {
+        self.extra.get("permission_mode").and_then(|v| v.as_str())
+    }
+
+    /// Look tool_input fields up under this JSON pointer when they aren't at the top level
+    pub fn with_input_root(self, input_root: Option<String>) -> Self {
+        HookInput { input_root, ..self }
+    }
+
+    /// Normalize path fields and cwd per this style before they are matched
+    pub fn with_path_style(self, path_style: PathStyle) -> Self {
+        HookInput { path_style, ..self }
+    }
+
+    /// A tool_input field holding a path, normalized per `path_style`
+    pub fn extract_path(&self, field_name: &str) -> Option<String> {
+        self.field_str(field_name).map(|path| normalize_path(path, self.path_style))
+    }
+
+    /// The input's working directory, normalized like path fields
+    pub fn cwd_path(&self) -> String {
+        normalize_path(&self.cwd, self.path_style)
+    }
+
+    /// A string field of tool_input, falling back to the object at `input_root`
     pub fn extract_field(&self, field_name: &str) -> Option<String> {
-        self.tool_input
-            .get(field_name)
-            .and_then(|v| v.as_str())
-            .map(|s| s.to_string())
+        self.field_str(field_name).map(|s| s.to_string())
     }
+
+    /// Like `extract_field`, but borrowed, for large fields such as written file content
+    pub fn field_str(&self, field_name: &str) -> Option<&str> {
+        let wrapped = || {
+            let root = self.input_root.as_deref()?;
+            self.tool_input.pointer(root)?.get(field_name)
+        };
+        self.tool_input.get(field_name).or_else(wrapped).and_then(|v| v.as_str())
+    }
+}
+
+/// Convert a Windows path to POSIX form for matching, as `style` says
+/// `C:\Users\me\.ssh` becomes `/c/Users/me/.ssh`; UNC paths keep their double slash
+pub fn normalize_path(path: &str, style: PathStyle) -> String {
+    let convert = match style {
+        PathStyle::Posix => false,
+        PathStyle::Windows => true,
+        PathStyle::Auto => looks_like_windows_path(path),
+    };
+    if !convert {
+        return path.to_string();
+    }
+    let path = path.replace('\\', "/");
+    match path.as_bytes() {
+        [drive, b':', rest @ ..] if drive.is_ascii_alphabetic() && (rest.is_empty() || rest[0] == b'/') => {
+            format!("/{}{}", drive.to_ascii_lowercase() as char, &path[2..])
+        }
+        _ => path,
+    }
+}
+
+/// A drive letter or UNC prefix, or backslash separators without any forward slash
+fn looks_like_windows_path(path: &str) -> bool {
+    let drive = matches!(path.as_bytes(), [letter, b':', b'\\' | b'/', ..] if letter.is_ascii_alphabetic());
+    drive || path.starts_with("\\\\") || (path.contains('\\') && !path.contains('/'))
+}
+
+/// Session id made safe for use as a file name: anything outside [A-Za-z0-9_-]
+/// is replaced, so a session id can never point outside a state directory
+pub fn session_file_stem(session_id: &str) -> String {
+    session_id
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+        .collect()
 }
 
 impl HookOutput {
@@ -63,6 +151,7 @@ impl HookOutput {
                 permission_decision_reason: reason,
             },
             suppress_output: true,
+            reason_detail: None,
         }
     }
 
@@ -74,14 +163,96 @@ impl HookOutput {
                 permission_decision_reason: reason,
             },
             suppress_output: true,
+            reason_detail: None,
         }
     }
 
-    pub fn write_to_stdout(&self) -> Result<()> {
-        let json = serde_json::to_string(self).context("Failed to serialize output to JSON")?;
-        io::stdout()
+    pub fn ask(reason: String) -> Self {
+        HookOutput {
+            hook_specific_output: HookSpecificOutput {
+                hook_event_name: "PreToolUse".to_string(),
+                permission_decision: "ask".to_string(),
+                permission_decision_reason: reason,
+            },
+            suppress_output: true,
+            reason_detail: None,
+        }
+    }
+
+    pub fn with_reason_detail(self, reason_detail: Option<ReasonDetail>) -> Self {
+        HookOutput { reason_detail, ..self }
+    }
+
+    /// Append the hook run's trace id to the reason, so a user report can be found in the logs
+    pub fn with_trace_id(mut self, trace_id: Option<&str>) -> Self {
+        if let Some(trace_id) = trace_id {
+            let reason = &mut self.hook_specific_output.permission_decision_reason;
+            *reason = format!("{} [trace_id: {}]", reason, trace_id);
+        }
+        self
+    }
+
+    /// Build the output JSON in the configured schema
+    pub fn to_json(&self, config: &OutputConfig) -> serde_json::Value {
+        let mut json = match config.schema {
+            OutputSchema::ClaudeCode => self.to_claude_code_json(&config.keys),
+            OutputSchema::Simple => serde_json::json!({
+                "decision": self.hook_specific_output.permission_decision,
+                "reason": self.hook_specific_output.permission_decision_reason,
+            }),
+        };
+        // Consumers ignore unknown top-level keys, so the detail sits beside the usual ones
+        if let Some(detail) = &self.reason_detail {
+            let key = match config.schema {
+                OutputSchema::ClaudeCode => "reasonDetail",
+                OutputSchema::Simple => "reason_detail",
+            };
+            json[key] = serde_json::json!(detail);
+        }
+        json
+    }
+
+    /// Build the Claude Code output JSON using the configured key names
+    fn to_claude_code_json(&self, keys: &OutputKeys) -> serde_json::Value {
+        let mut specific = serde_json::Map::new();
+        specific.insert(
+            keys.hook_event_name.clone(),
+            self.hook_specific_output.hook_event_name.clone().into(),
+        );
+        specific.insert(
+            keys.permission_decision.clone(),
+            self.hook_specific_output.permission_decision.clone().into(),
+        );
+        specific.insert(
+            keys.permission_decision_reason.clone(),
+            self.hook_specific_output.permission_decision_reason.clone().into(),
+        );
+
+        let mut root = serde_json::Map::new();
+        root.insert(keys.hook_specific_output.clone(), specific.into());
+        root.insert(keys.suppress_output.clone(), self.suppress_output.into());
+        root.into()
+    }
+
+    /// Write the decision to whichever of `stdout` and `stderr` `output.stream` chooses
+    pub fn write_to_stream(
+        &self,
+        config: &OutputConfig,
+        stdout: &mut impl Write,
+        stderr: &mut impl Write,
+    ) -> Result<()> {
+        match config.stream {
+            OutputStream::Stdout => self.write_to(stdout, config),
+            OutputStream::Stderr => self.write_to(stderr, config),
+        }
+    }
+
+    pub fn write_to(&self, writer: &mut impl Write, config: &OutputConfig) -> Result<()> {
+        let json = serde_json::to_string(&self.to_json(config))
+            .context("Failed to serialize output to JSON")?;
+        writer
             .write_all(json.as_bytes())
-            .context("Failed to write to stdout")?;
+            .context("Failed to write decision output")?;
         Ok(())
     }
 }
@@ -102,6 +273,9 @@ mod tests {
             tool_input: serde_json::json!({
                 "file_path": "/home/user/test.txt"
             }),
+            extra: serde_json::Map::new(),
+            input_root: None,
+            path_style: Default::default(),
         };
 
         assert_eq!(
@@ -111,6 +285,51 @@ mod tests {
         assert_eq!(input.extract_field("nonexistent"), None);
     }
 
+    #[test]
+    fn test_extract_field_under_input_root() {
+        let input = HookInput {
+            session_id: "test".to_string(),
+            transcript_path: "/tmp/test".to_string(),
+            cwd: "/home/user".to_string(),
+            hook_event_name: "PreToolUse".to_string(),
+            tool_name: "mcp__files__read".to_string(),
+            tool_input: serde_json::json!({
+                "mode": "text",
+                "params": {"file_path": "/home/user/.env", "mode": "binary"}
+            }),
+            extra: serde_json::Map::new(),
+            input_root: None,
+            path_style: Default::default(),
+        };
+        assert_eq!(input.extract_field("file_path"), None);
+
+        let input = input.with_input_root(Some("/params".to_string()));
+        assert_eq!(input.extract_field("file_path"), Some("/home/user/.env".to_string()));
+        // Top-level fields still win over wrapped ones
+        assert_eq!(input.extract_field("mode"), Some("text".to_string()));
+
+        let input = input.with_input_root(Some("/arguments".to_string()));
+        assert_eq!(input.extract_field("file_path"), None);
+    }
+
+    #[test]
+    fn test_normalize_path() {
+        let windows = |path| normalize_path(path, PathStyle::Windows);
+        assert_eq!(windows(r"C:\Users\me\.ssh\id_rsa"), "/c/Users/me/.ssh/id_rsa");
+        assert_eq!(windows("D:/work/project"), "/d/work/project");
+        assert_eq!(windows(r"\\server\share\file.txt"), "//server/share/file.txt");
+        assert_eq!(windows(r"src\main.rs"), "src/main.rs");
+
+        let auto = |path| normalize_path(path, PathStyle::Auto);
+        assert_eq!(auto(r"C:\Users\me"), "/c/Users/me");
+        assert_eq!(auto(r"src\main.rs"), "src/main.rs");
+        // POSIX paths, including ones with an escaped character, are left alone
+        assert_eq!(auto("/home/me/file.txt"), "/home/me/file.txt");
+        assert_eq!(auto(r"/home/me/odd\ name"), r"/home/me/odd\ name");
+
+        assert_eq!(normalize_path(r"C:\Users\me", PathStyle::Posix), r"C:\Users\me");
+    }
+
     #[test]
     fn test_hook_output_serialization() -> Result<()> {
         let output = HookOutput::allow("Test reason".to_string());
@@ -125,4 +344,73 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_hook_output_custom_keys() {
+        let config = OutputConfig {
+            keys: OutputKeys {
+                hook_specific_output: "hookOutput".to_string(),
+                permission_decision: "decision".to_string(),
+                ..OutputKeys::default()
+            },
+            ..OutputConfig::default()
+        };
+        let json = HookOutput::deny("Blocked".to_string()).to_json(&config);
+
+        assert_eq!(json["hookOutput"]["decision"], "deny");
+        assert_eq!(json["hookOutput"]["permissionDecisionReason"], "Blocked");
+        assert_eq!(json["suppressOutput"], true);
+        assert!(json.get("hookSpecificOutput").is_none());
+    }
+
+    #[test]
+    fn test_hook_output_reason_detail() {
+        let detail = ReasonDetail {
+            source: "rule".to_string(),
+            rule_id: Some("deny-rm".to_string()),
+            matched_pattern: Some("command_regex '^rm '".to_string()),
+            risk_level: "low".to_string(),
+        };
+        let output = HookOutput::deny("Blocked".to_string()).with_reason_detail(Some(detail));
+        let expected = serde_json::json!({
+            "source": "rule",
+            "rule_id": "deny-rm",
+            "matched_pattern": "command_regex '^rm '",
+            "risk_level": "low",
+        });
+
+        let json = output.to_json(&OutputConfig::default());
+        assert_eq!(json["reasonDetail"], expected);
+        assert_eq!(json["hookSpecificOutput"]["permissionDecisionReason"], "Blocked");
+
+        let simple = OutputConfig {
+            schema: OutputSchema::Simple,
+            ..OutputConfig::default()
+        };
+        assert_eq!(output.to_json(&simple)["reason_detail"], expected);
+
+        let plain = HookOutput::deny("Blocked".to_string()).to_json(&OutputConfig::default());
+        assert!(plain.get("reasonDetail").is_none());
+    }
+
+    #[test]
+    fn test_hook_output_default_keys_match_serde() -> Result<()> {
+        let output = HookOutput::allow("Test reason".to_string());
+        assert_eq!(output.to_json(&OutputConfig::default()), serde_json::to_value(&output)?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_hook_output_simple_schema() {
+        let config = OutputConfig {
+            schema: OutputSchema::Simple,
+            ..OutputConfig::default()
+        };
+        let json = HookOutput::deny("Blocked".to_string()).to_json(&config);
+        assert_eq!(json, serde_json::json!({"decision": "deny", "reason": "Blocked"}));
+
+        let mut written = Vec::new();
+        HookOutput::allow("Safe".to_string()).write_to(&mut written, &config).unwrap();
+        assert_eq!(String::from_utf8(written).unwrap(), r#"{"decision":"allow","reason":"Safe"}"#);
+    }
 }
@@ -1,9 +1,10 @@
 #![forbid(unsafe_code)]
 #![warn(clippy::all)]
 
-use crate::config::LlmFallbackConfig;
+use crate::config::{Consensus, LlmFallbackConfig, QueryDecision};
+use crate::history::{self, HistoryEntry};
 use crate::hook_io::{HookInput, HookOutput};
-use crate::logging::{create_llm_metadata, LlmMetadata};
+use crate::logging::{create_llm_metadata, LlmAttempt, LlmMetadata, ModelVerdict};
 use anyhow::{Context, Result};
 use log::{debug, error, info, warn};
 use regex::Regex;
@@ -17,9 +18,26 @@ pub enum SafetyAssessment {
     Query(String),  // reasoning - needs user review (unsafe, ambiguous, or uncertain)
 }
 
+/// Parsed model response: the classification plus any risk factors it listed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LlmVerdict {
+    pub assessment: SafetyAssessment,
+    pub concerns: Vec<String>,
+    /// Each model's own verdict when several `models` were asked, in config order
+    pub model_verdicts: Vec<ModelVerdict>,
+    /// Model that answered, as the endpoint reported it, when known
+    pub model: Option<String>,
+    /// Upstream provider that served the request (OpenRouter reports it), when known
+    pub provider: Option<String>,
+    /// Every response behind this verdict, when `record_attempts` is on
+    pub attempts: Vec<LlmAttempt>,
+    /// The prompt sent, when `record_prompt` is on
+    pub prompt: Option<String>,
+}
+
 #[derive(Debug)]
 pub enum AssessmentResult {
-    Assessment(SafetyAssessment),
+    Assessment(LlmVerdict),
     Timeout,
     Error(String),
 }
@@ -28,87 +46,277 @@ pub enum AssessmentResult {
 struct LlmResponse {
     classification: String,
     reasoning: String,
+    // Optional so models that only return classification/reasoning still parse
+    #[serde(default)]
+    concerns: Vec<String>,
+}
+
+/// Source of verdicts for inputs that no rule decided (and for escalated rules)
+/// The hook uses `HttpClassifier`; anything else implementing this can drive the
+/// same decision flow, e.g. a local model or fixed heuristics
+/// `hints` are the `llm_hint`s of the rules involved, as policy context for the verdict
+pub trait SafetyClassifier {
+    fn assess(&self, input: &HookInput, hints: &[String]) -> impl Future<Output = AssessmentResult> + Send;
+}
+
+/// The configured LLM endpoint, asking one model or several for a consensus
+/// Honors `mock_response` so configs can be tested without a live model
+pub struct HttpClassifier<'a> {
+    config: &'a LlmFallbackConfig,
+}
+
+impl<'a> HttpClassifier<'a> {
+    pub fn new(config: &'a LlmFallbackConfig) -> Self {
+        HttpClassifier { config }
+    }
+}
+
+impl SafetyClassifier for HttpClassifier<'_> {
+    async fn assess(&self, input: &HookInput, hints: &[String]) -> AssessmentResult {
+        let config = self.config;
+        if let Some(mock) = &config.mock_response {
+            warn!("llm_fallback.mock_response is set - using a fixed verdict (testing only)");
+            return match classify(&mock.classification, mock.reasoning.clone(), mock.concerns.clone()) {
+                Ok(verdict) => AssessmentResult::Assessment(verdict),
+                Err(e) => AssessmentResult::Error(e.to_string()),
+            };
+        }
+
+        let models = config.model_names();
+        if models.len() > 1 {
+            assess_with_consensus(config, input, hints, models).await
+        } else {
+            let model = models.into_iter().next().unwrap_or_default();
+            assess_with_model(config, &model, input, hints).await
+        }
+    }
 }
 
 /// Main entry point for LLM safety assessment
 /// Returns (result, processing_time_ms)
 pub async fn assess_with_llm(config: &LlmFallbackConfig, input: &HookInput) -> (AssessmentResult, u64) {
+    assess_with(&HttpClassifier::new(config), input, &[]).await
+}
+
+/// Assess with any classifier, timing the call
+/// Returns (result, processing_time_ms)
+pub async fn assess_with(
+    classifier: &impl SafetyClassifier,
+    input: &HookInput,
+    hints: &[String],
+) -> (AssessmentResult, u64) {
     debug!("Starting LLM assessment for {}", input.tool_name);
 
+    let start = Instant::now();
+    let assessment_result = classifier.assess(input, hints).await;
+    let processing_time_ms = start.elapsed().as_millis() as u64;
+
+    (assessment_result, processing_time_ms)
+}
+
+/// Ask one model, bounded by `timeout_secs`
+async fn assess_with_model(
+    config: &LlmFallbackConfig,
+    model: &str,
+    input: &HookInput,
+    hints: &[String],
+) -> AssessmentResult {
     let start = Instant::now();
 
     let result = timeout(
         Duration::from_secs(config.timeout_secs),
-        call_llm(config, input),
+        call_llm(config, model, input, hints),
     )
     .await;
 
     let processing_time_ms = start.elapsed().as_millis() as u64;
 
-    let assessment_result = match result {
-        Ok(Ok(assessment)) => {
-            debug!("LLM assessment completed in {}ms: {:?}", processing_time_ms, assessment);
-            AssessmentResult::Assessment(assessment)
+    match result {
+        Ok(Ok(verdict)) => {
+            debug!("LLM {} assessment completed in {}ms: {:?}", model, processing_time_ms, verdict);
+            AssessmentResult::Assessment(verdict)
         }
         Ok(Err(e)) => {
-            error!("LLM call failed after {}ms: {}", processing_time_ms, e);
+            error!("LLM {} call failed after {}ms: {}", model, processing_time_ms, e);
             AssessmentResult::Error(e.to_string())
         }
         Err(_) => {
-            warn!("LLM timeout after {}ms", processing_time_ms);
+            warn!("LLM {} timeout after {}ms", model, processing_time_ms);
             AssessmentResult::Timeout
         }
-    };
+    }
+}
 
-    (assessment_result, processing_time_ms)
+/// Ask every model concurrently and combine their verdicts per `config.consensus`
+async fn assess_with_consensus(
+    config: &LlmFallbackConfig,
+    input: &HookInput,
+    hints: &[String],
+    models: Vec<String>,
+) -> AssessmentResult {
+    let mut tasks = tokio::task::JoinSet::new();
+    for (idx, model) in models.iter().enumerate() {
+        let (config, input, model, hints) = (config.clone(), input.clone(), model.clone(), hints.to_vec());
+        tasks.spawn(async move { (idx, assess_with_model(&config, &model, &input, &hints).await) });
+    }
+    let mut results = tasks.join_all().await;
+    results.sort_by_key(|(idx, _)| *idx);
+
+    let results = models.into_iter().zip(results.into_iter().map(|(_, result)| result)).collect();
+    combine_verdicts(config.consensus, results)
+}
+
+/// Combine per-model results; a timeout or error counts as a vote against allowing
+/// Only when no model produced a verdict at all is the combined result a timeout or error
+fn combine_verdicts(consensus: Consensus, results: Vec<(String, AssessmentResult)>) -> AssessmentResult {
+    if !results.iter().any(|(_, r)| matches!(r, AssessmentResult::Assessment(_))) {
+        if results.iter().all(|(_, r)| matches!(r, AssessmentResult::Timeout)) {
+            return AssessmentResult::Timeout;
+        }
+        let errors: Vec<String> = results
+            .iter()
+            .map(|(model, r)| match r {
+                AssessmentResult::Error(e) => format!("{}: {}", model, e),
+                _ => format!("{}: timeout", model),
+            })
+            .collect();
+        return AssessmentResult::Error(format!("All models failed - {}", errors.join("; ")));
+    }
+
+    let total = results.len();
+    let mut model_verdicts = Vec::new();
+    let mut concerns: Vec<String> = Vec::new();
+    let mut attempts = Vec::new();
+    let mut prompt = None;
+    let (mut allow_reasons, mut other_reasons) = (Vec::new(), Vec::new());
+    for (model, result) in results {
+        let (assessment, reasoning) = match result {
+            AssessmentResult::Assessment(verdict) => {
+                attempts.extend(verdict.attempts);
+                // Every model is sent the same prompt
+                prompt = prompt.or(verdict.prompt);
+                for concern in verdict.concerns {
+                    if !concerns.contains(&concern) {
+                        concerns.push(concern);
+                    }
+                }
+                match verdict.assessment {
+                    SafetyAssessment::Allow(r) => ("ALLOW", r),
+                    SafetyAssessment::Query(r) => ("QUERY", r),
+                }
+            }
+            AssessmentResult::Timeout => ("TIMEOUT", "Request timed out".to_string()),
+            AssessmentResult::Error(e) => ("ERROR", e),
+        };
+        let summary = format!("{} ({}): {}", model, assessment, reasoning);
+        if assessment == "ALLOW" {
+            allow_reasons.push(summary);
+        } else {
+            other_reasons.push(summary);
+        }
+        model_verdicts.push(ModelVerdict {
+            model,
+            assessment: assessment.to_string(),
+            reasoning,
+        });
+    }
+
+    let allows = allow_reasons.len();
+    let required = match consensus {
+        Consensus::All => total,
+        Consensus::Majority => total / 2 + 1,
+    };
+    let assessment = if allows >= required {
+        SafetyAssessment::Allow(format!("{}/{} models allowed - {}", allows, total, allow_reasons.join("; ")))
+    } else {
+        SafetyAssessment::Query(format!(
+            "{}/{} models allowed, '{}' consensus not reached - {}",
+            allows,
+            total,
+            consensus,
+            other_reasons.join("; ")
+        ))
+    };
+    AssessmentResult::Assessment(LlmVerdict {
+        assessment,
+        concerns,
+        model_verdicts,
+        model: None,
+        provider: None,
+        attempts,
+        prompt,
+    })
 }
 
 /// Apply LLM result and create metadata
-/// Returns Option<(HookOutput, LlmMetadata)>
+/// Returns (Option<HookOutput>, LlmMetadata) - no output means passthrough,
+/// but the metadata is always returned so the review log keeps the LLM's reasoning
+/// `configured_model` is recorded when the verdict doesn't say which model answered
+/// (and for timeouts and errors); see `LlmFallbackConfig::model_label`
+/// Outside test mode a QUERY verdict follows `query_decision`
 pub fn apply_llm_result(
     _input: &HookInput,
     result: (AssessmentResult, u64),
+    configured_model: &str,
     test_mode: bool,
-) -> Option<(HookOutput, LlmMetadata)> {
+    query_decision: QueryDecision,
+) -> (Option<HookOutput>, LlmMetadata) {
     use AssessmentResult::*;
     use SafetyAssessment::*;
 
     let (assessment_result, processing_time_ms) = result;
-
-    // Get model from config - simplified for now
-    let model = "llm-fallback".to_string();
+    let model = configured_model.to_string();
 
     match assessment_result {
-        Assessment(Allow(r)) => {
+        Assessment(LlmVerdict {
+            assessment: Allow(r),
+            concerns,
+            model_verdicts,
+            model: answered,
+            provider,
+            attempts,
+            prompt,
+        }) => {
             let reasoning = format!("LLM: {}", r);
             info!("LLM Allow: {}", reasoning);
             let hook_output = HookOutput::allow(reasoning.clone());
-            let metadata = create_llm_metadata(
-                "ALLOW",
-                &r,
-                &model,
-                Some(processing_time_ms),
-                None,
-            );
-            Some((hook_output, metadata))
+            let model = answered.unwrap_or(model);
+            let metadata = LlmMetadata {
+                model_verdicts,
+                provider,
+                attempts,
+                prompt,
+                ..create_llm_metadata("ALLOW", &r, &model, Some(processing_time_ms), None, concerns)
+            };
+            (Some(hook_output), metadata)
         }
-        Assessment(Query(r)) => {
+        Assessment(LlmVerdict {
+            assessment: Query(r),
+            concerns,
+            model_verdicts,
+            model: answered,
+            provider,
+            attempts,
+            prompt,
+        }) => {
             let reasoning = format!("LLM Query: {}", r);
             info!("{}", reasoning);
-            let hook_output = HookOutput::deny(reasoning.clone());
-            let metadata = create_llm_metadata(
-                "QUERY",
-                &r,
-                &model,
-                Some(processing_time_ms),
-                None,
-            );
-            // In test mode, output; otherwise pass through
-            if test_mode {
-                Some((hook_output, metadata))
-            } else {
-                None
-            }
+            let model = answered.unwrap_or(model);
+            let metadata = LlmMetadata {
+                model_verdicts,
+                provider,
+                attempts,
+                prompt,
+                ..create_llm_metadata("QUERY", &r, &model, Some(processing_time_ms), None, concerns)
+            };
+            // In test mode, deny; otherwise follow the configured query decision
+            let hook_output = match query_decision {
+                _ if test_mode => Some(HookOutput::deny(reasoning)),
+                QueryDecision::Passthrough => None,
+                QueryDecision::Ask => Some(HookOutput::ask(reasoning)),
+                QueryDecision::Deny => Some(HookOutput::deny(reasoning)),
+            };
+            (hook_output, metadata)
         }
         Timeout => {
             warn!("LLM timeout");
@@ -119,12 +327,9 @@ pub fn apply_llm_result(
                 &model,
                 Some(processing_time_ms),
                 None,
+                Vec::new(),
             );
-            if test_mode {
-                Some((hook_output, metadata))
-            } else {
-                None
-            }
+            (test_mode.then_some(hook_output), metadata)
         }
         Error(e) => {
             error!("LLM error: {}", e);
@@ -135,24 +340,28 @@ pub fn apply_llm_result(
                 &model,
                 Some(processing_time_ms),
                 None,
+                Vec::new(),
             );
-            if test_mode {
-                Some((hook_output, metadata))
-            } else {
-                None
-            }
+            (test_mode.then_some(hook_output), metadata)
         }
     }
 }
 
-async fn call_llm(config: &LlmFallbackConfig, input: &HookInput) -> Result<SafetyAssessment> {
+async fn call_llm(config: &LlmFallbackConfig, model: &str, input: &HookInput, hints: &[String]) -> Result<LlmVerdict> {
     // Validate configuration (should have been caught by validate command, but double-check)
     let endpoint = config.endpoint.as_ref()
         .context("LLM endpoint not configured - this should have been caught during validation")?;
-    let model = config.model.as_ref()
-        .context("LLM model not configured - this should have been caught during validation")?;
+    config.check_endpoint_tls(endpoint)?;
+    if model.is_empty() {
+        anyhow::bail!("LLM model not configured - this should have been caught during validation");
+    }
+
+    let prompt = build_safety_prompt(input, &history::recent(config, &input.session_id), hints);
 
-    let prompt = build_safety_prompt(input);
+    // Index of the provider currently at the front of the preference order
+    let mut provider_rotation = 0;
+    // Every response parsed, kept for llm_metadata when record_attempts is on
+    let mut attempts = Vec::new();
 
     // Retry loop for malformed JSON responses
     for attempt in 0..=config.max_retries {
@@ -162,72 +371,62 @@ async fn call_llm(config: &LlmFallbackConfig, input: &HookInput) -> Result<Safet
 
         debug!("LLM prompt (attempt {}):\n{}", attempt + 1, prompt);
 
-        // Build request JSON
-        // Note: keep_alive doesn't work with OpenAI-compatible endpoint
-        // Set OLLAMA_KEEP_ALIVE=1h environment variable for Ollama instead
-        let mut request_json = serde_json::json!({
-            "model": model,
-            "temperature": config.temperature,
-            "messages": [
-                {
-                    "role": "system",
-                    "content": config.system_prompt
-                },
-                {
-                    "role": "user",
-                    "content": prompt
+        // Rate-limit rotations happen here and do not count against max_retries
+        let response = loop {
+            let request_json = build_request_json(config, model, &prompt, provider_rotation);
+
+            let request_payload = serde_json::to_string_pretty(&request_json).unwrap_or_default();
+            info!("=== REQUEST PAYLOAD ===\n{}", request_payload);
+            info!("=== END PAYLOAD ===");
+
+            // Make HTTP request
+            info!("Sending request to: {}/chat/completions", endpoint);
+            info!("API key present: {}", config.api_key.as_ref().map_or("NO", |k| if k.is_empty() { "EMPTY" } else { "YES" }));
+            info!("Timeout: {} seconds", config.timeout_secs);
+
+            let response = reqwest::Client::new()
+                        .post(format!("{}/chat/completions", endpoint))
+                        .header("Content-Type", "application/json")
+                        .header("Authorization", format!("Bearer {}", config.api_key.as_deref().unwrap_or("")))
+                        .json(&request_json)
+                        .timeout(std::time::Duration::from_secs(config.timeout_secs))
+                        .send()
+                .await;
+
+            let response = match response {
+                Ok(resp) => {
+                    info!("HTTP status: {}", resp.status());
+                    resp
                 }
-            ]
-        });
-        
-        // Add provider preferences if specified (OpenRouter-specific)
-        if let Some(ref providers) = config.provider_preferences {
-            if !providers.is_empty() {
-                if let Some(obj) = request_json.as_object_mut() {
-                    obj.insert(
-                        "provider".to_string(),
-                        serde_json::json!({"order": providers})
-                    );
+                Err(e) => {
+                    if e.is_timeout() {
+                        error!("Request TIMEOUT after {} seconds", config.timeout_secs);
+                    } else if e.is_connect() {
+                        error!("Connection failed: {}", e);
+                    } else {
+                        error!("Request failed: {}", e);
+                    }
+                    error!("Full error details: {:?}", e);
+                    return Err(anyhow::anyhow!("Failed to send LLM request: {}", e));
                 }
+            };
+
+            if response.status() != reqwest::StatusCode::TOO_MANY_REQUESTS {
+                break response;
             }
-        }
-        
-        let request_payload = serde_json::to_string_pretty(&request_json).unwrap_or_default();
-        info!("=== REQUEST PAYLOAD ===\n{}", request_payload);
-        info!("=== END PAYLOAD ===");
-
-        // Make HTTP request
-        info!("Sending request to: {}/chat/completions", endpoint);
-        info!("API key present: {}", config.api_key.as_ref().map_or("NO", |k| if k.is_empty() { "EMPTY" } else { "YES" }));
-        info!("Timeout: {} seconds", config.timeout_secs);
-
-        let response = reqwest::Client::new()
-                    .post(format!("{}/chat/completions", endpoint))
-                    .header("Content-Type", "application/json")
-                    .header("Authorization", format!("Bearer {}", config.api_key.as_deref().unwrap_or("")))
-                    .json(&request_json)
-                    .timeout(std::time::Duration::from_secs(config.timeout_secs))
-                    .send()
-            .await;
-        
-        let response = match response {
-            Ok(resp) => {
-                info!("HTTP status: {}", resp.status());
-                resp
-            }
-            Err(e) => {
-                if e.is_timeout() {
-                    error!("Request TIMEOUT after {} seconds", config.timeout_secs);
-                } else if e.is_connect() {
-                    error!("Connection failed: {}", e);
-                } else {
-                    error!("Request failed: {}", e);
-                }
-                error!("Full error details: {:?}", e);
-                return Err(anyhow::anyhow!("Failed to send LLM request: {}", e));
+
+            let providers = config.provider_preferences.as_deref().unwrap_or_default();
+            if provider_rotation + 1 >= providers.len() {
+                anyhow::bail!("LLM endpoint rate limited (HTTP 429) and no other providers to try");
             }
+            warn!(
+                "HTTP 429 from provider '{}' - rotating to '{}'",
+                providers[provider_rotation],
+                providers[provider_rotation + 1]
+            );
+            provider_rotation += 1;
         };
-        
+
         let response_text = match response.text().await {
             Ok(text) => {
                 debug!("Response length: {} chars", text.len());
@@ -250,12 +449,32 @@ async fn call_llm(config: &LlmFallbackConfig, input: &HookInput) -> Result<Safet
         
         debug!("LLM raw response (attempt {}): {}", attempt + 1, content);
 
-        match parse_llm_response(content) {
-            Ok(assessment) => {
+        let parsed = parse_llm_response(content, config.strict_json);
+        if config.record_attempts {
+            attempts.push(LlmAttempt {
+                model: model.to_string(),
+                attempt: attempt + 1,
+                content: content.to_string(),
+                classification: parsed.as_ref().ok().map(|verdict| match verdict.assessment {
+                    SafetyAssessment::Allow(_) => "ALLOW".to_string(),
+                    SafetyAssessment::Query(_) => "QUERY".to_string(),
+                }),
+                error: parsed.as_ref().err().map(|e| format!("{:#}", e)),
+            });
+        }
+
+        match parsed {
+            Ok(mut verdict) => {
+                // Routers may substitute a model variant; record what actually answered
+                let answered_by = |key: &str| api_response[key].as_str().filter(|v| !v.is_empty()).map(str::to_string);
+                verdict.model = Some(answered_by("model").unwrap_or_else(|| model.to_string()));
+                verdict.provider = answered_by("provider");
+                verdict.attempts = attempts;
+                verdict.prompt = config.record_prompt.then(|| prompt.clone());
                 if attempt > 0 {
                     info!("LLM succeeded after {} retries", attempt);
                 }
-                return Ok(assessment);
+                return Ok(verdict);
             }
             Err(e) => {
                 if attempt < config.max_retries {
@@ -274,26 +493,77 @@ async fn call_llm(config: &LlmFallbackConfig, input: &HookInput) -> Result<Safet
     unreachable!()
 }
 
-fn build_safety_prompt(input: &HookInput) -> String {
+/// Build the chat completions request, rotating provider preferences so that
+/// the provider at `provider_rotation` is tried first
+fn build_request_json(
+    config: &LlmFallbackConfig,
+    model: &str,
+    prompt: &str,
+    provider_rotation: usize,
+) -> serde_json::Value {
+    // Note: keep_alive doesn't work with OpenAI-compatible endpoint
+    // Set OLLAMA_KEEP_ALIVE=1h environment variable for Ollama instead
+    let mut request_json = serde_json::json!({
+        "model": model,
+        "temperature": config.temperature,
+        "messages": [
+            {
+                "role": "system",
+                "content": config.system_prompt
+            },
+            {
+                "role": "user",
+                "content": prompt
+            }
+        ]
+    });
+
+    // Add provider preferences if specified (OpenRouter-specific)
+    if let Some(ref providers) = config.provider_preferences
+        && !providers.is_empty()
+        && let Some(obj) = request_json.as_object_mut()
+    {
+        let mut order = providers.clone();
+        order.rotate_left(provider_rotation.min(providers.len()));
+        obj.insert(
+            "provider".to_string(),
+            serde_json::json!({"order": order})
+        );
+    }
+
+    request_json
+}
+
+fn build_safety_prompt(input: &HookInput, history: &[HistoryEntry], hints: &[String]) -> String {
     let params =
         serde_json::to_string_pretty(&input.tool_input).unwrap_or_else(|_| "{}".to_string());
 
-    format!(r#"Evaluate this tool use request:
+    format!(r#"{}Evaluate this tool use request:
 
 Tool: {}
 Parameters:
 {}
-
-Classify as ALLOW or QUERY following your instructions above. Respond in this exact JSON format:
+{}
+Classify as ALLOW or QUERY following your instructions above. List each specific risk factor you considered in "concerns" (use an empty list if there are none). Respond in this exact JSON format:
 {{
   "classification": "ALLOW|QUERY",
-  "reasoning": "brief explanation"
+  "reasoning": "brief explanation",
+  "concerns": ["specific risk factor"]
 }}"#,
-        input.tool_name, params
+        history::format_for_prompt(history), input.tool_name, params, format_hints(hints)
     )
 }
 
-fn parse_llm_response(content: &str) -> Result<SafetyAssessment> {
+/// Rule-provided policy context, as a block that ends in a blank line; empty without hints
+fn format_hints(hints: &[String]) -> String {
+    if hints.is_empty() {
+        return String::new();
+    }
+    let lines: Vec<String> = hints.iter().map(|hint| format!("- {}", hint)).collect();
+    format!("\nPolicy notes from the configuration:\n{}\n", lines.join("\n"))
+}
+
+fn parse_llm_response(content: &str, strict_json: bool) -> Result<LlmVerdict> {
     // Extract JSON object using regex (finds content between outermost { })
     let json_regex = Regex::new(r"(?s)\{.*\}").context("Failed to compile JSON regex")?;
     
@@ -307,6 +577,9 @@ fn parse_llm_response(content: &str) -> Result<SafetyAssessment> {
     // Try direct parse first
     let response = match serde_json::from_str::<LlmResponse>(json_str) {
         Ok(r) => r,
+        Err(e) if strict_json => {
+            return Err(e).context("Failed to parse JSON (strict_json enabled, repair skipped)");
+        }
         Err(e) => {
             // Try simple repairs for common issues
             let repaired = simple_json_repair(json_str);
@@ -317,12 +590,27 @@ fn parse_llm_response(content: &str) -> Result<SafetyAssessment> {
         }
     };
 
-    // Validate and classify
-    match response.classification.to_uppercase().as_str() {
-        "ALLOW" => Ok(SafetyAssessment::Allow(response.reasoning)),
-        "QUERY" => Ok(SafetyAssessment::Query(response.reasoning)),
+    classify(&response.classification, response.reasoning, response.concerns)
+}
+
+/// Validate a classification label and build the verdict
+fn classify(classification: &str, reasoning: String, concerns: Vec<String>) -> Result<LlmVerdict> {
+    let assessment = match classification.to_uppercase().as_str() {
+        "ALLOW" => SafetyAssessment::Allow(reasoning),
+        // UNKNOWN is the legacy three-way label; treat it as needing review
+        "QUERY" | "UNKNOWN" => SafetyAssessment::Query(reasoning),
         other => anyhow::bail!("Invalid classification '{}' - must be ALLOW or QUERY", other),
-    }
+    };
+
+    Ok(LlmVerdict {
+        assessment,
+        concerns,
+        model_verdicts: Vec::new(),
+        model: None,
+        provider: None,
+        attempts: Vec::new(),
+        prompt: None,
+    })
 }
 
 /// Apply simple JSON repairs for common LLM mistakes
@@ -338,13 +626,14 @@ fn simple_json_repair(json: &str) -> String {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_support::{chat_completion, spawn_mock_llm};
 
     #[test]
     fn test_parse_llm_response_plain() {
         let json = r#"{"classification": "ALLOW", "reasoning": "Read-only operation"}"#;
-        let result = parse_llm_response(json).unwrap();
+        let result = parse_llm_response(json, false).unwrap();
         assert_eq!(
-            result,
+            result.assessment,
             SafetyAssessment::Allow("Read-only operation".to_string())
         );
     }
@@ -354,9 +643,9 @@ mod tests {
         let response = r#"Sure, here's my assessment:
 {"classification": "QUERY", "reasoning": "Destructive command"}
 Hope this helps!"#;
-        let result = parse_llm_response(response).unwrap();
+        let result = parse_llm_response(response, false).unwrap();
         assert_eq!(
-            result,
+            result.assessment,
             SafetyAssessment::Query("Destructive command".to_string())
         );
     }
@@ -366,9 +655,9 @@ Hope this helps!"#;
         let json = r#"```json
 {"classification": "ALLOW", "reasoning": "Safe operation"}
 ```"#;
-        let result = parse_llm_response(json).unwrap();
+        let result = parse_llm_response(json, false).unwrap();
         assert_eq!(
-            result,
+            result.assessment,
             SafetyAssessment::Allow("Safe operation".to_string())
         );
     }
@@ -377,33 +666,371 @@ Hope this helps!"#;
     fn test_parse_llm_response_malformed_json() {
         // Trailing comma - simple_json_repair should fix this
         let json = r#"{"classification": "QUERY", "reasoning": "Cannot determine",}"#;
-        let result = parse_llm_response(json).unwrap();
+        let result = parse_llm_response(json, false).unwrap();
         assert_eq!(
-            result,
+            result.assessment,
             SafetyAssessment::Query("Cannot determine".to_string())
         );
     }
 
+    #[test]
+    fn test_parse_llm_response_strict_rejects_malformed_json() {
+        let json = r#"{"classification": "QUERY", "reasoning": "Cannot determine",}"#;
+        assert!(parse_llm_response(json, true).is_err());
+
+        let valid = r#"{"classification": "QUERY", "reasoning": "Cannot determine"}"#;
+        assert!(parse_llm_response(valid, true).is_ok());
+    }
+
     #[test]
     fn test_parse_llm_response_legacy_unknown() {
         // Test legacy UNKNOWN classification (maps to Query)
         let json = r#"{"classification": "UNKNOWN", "reasoning": "Cannot determine"}"#;
-        let result = parse_llm_response(json).unwrap();
+        let result = parse_llm_response(json, false).unwrap();
         assert_eq!(
-            result,
+            result.assessment,
             SafetyAssessment::Query("Cannot determine".to_string())
         );
     }
 
+    #[test]
+    fn test_parse_llm_response_with_concerns() {
+        let json = r#"{"classification": "QUERY", "reasoning": "Network access", "concerns": ["downloads remote script", "pipes to shell"]}"#;
+        let result = parse_llm_response(json, false).unwrap();
+        assert_eq!(
+            result.concerns,
+            vec!["downloads remote script".to_string(), "pipes to shell".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_parse_llm_response_without_concerns() {
+        let json = r#"{"classification": "ALLOW", "reasoning": "Read-only operation"}"#;
+        let result = parse_llm_response(json, false).unwrap();
+        assert!(result.concerns.is_empty());
+    }
+
     #[test]
     fn test_parse_llm_response_invalid_classification() {
         let json = r#"{"classification": "MAYBE", "reasoning": "Unsure"}"#;
-        assert!(parse_llm_response(json).is_err());
+        assert!(parse_llm_response(json, false).is_err());
     }
 
     #[test]
     fn test_parse_llm_response_no_json() {
         let response = "This is just plain text without any JSON";
-        assert!(parse_llm_response(response).is_err());
+        assert!(parse_llm_response(response, false).is_err());
+    }
+
+    fn test_input() -> HookInput {
+        HookInput {
+            session_id: "test".to_string(),
+            transcript_path: "/tmp/test".to_string(),
+            cwd: "/home/user/project".to_string(),
+            hook_event_name: "PreToolUse".to_string(),
+            tool_name: "Bash".to_string(),
+            tool_input: serde_json::json!({"command": "cargo test"}),
+            extra: serde_json::Map::new(),
+            input_root: None,
+            path_style: Default::default(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_call_llm_rotates_provider_on_429() {
+        let (endpoint, requests) = spawn_mock_llm(vec![
+            (429, r#"{"error": "rate limited"}"#.to_string()),
+            (200, chat_completion(r#"{"classification": "ALLOW", "reasoning": "Dev command"}"#)),
+        ]);
+        let config = LlmFallbackConfig {
+            enabled: true,
+            endpoint: Some(endpoint),
+            model: Some("test-model".to_string()),
+            max_retries: 0,
+            provider_preferences: Some(vec!["first".to_string(), "second".to_string()]),
+            ..LlmFallbackConfig::default()
+        };
+
+        let verdict = call_llm(&config, "test-model", &test_input(), &[]).await.unwrap();
+        assert_eq!(verdict.assessment, SafetyAssessment::Allow("Dev command".to_string()));
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+        let second: serde_json::Value = serde_json::from_str(&requests[1]).unwrap();
+        assert_eq!(second["provider"]["order"], serde_json::json!(["second", "first"]));
+    }
+
+    #[tokio::test]
+    async fn test_call_llm_429_without_alternate_provider_errors() {
+        let (endpoint, _) = spawn_mock_llm(vec![(429, "{}".to_string())]);
+        let config = LlmFallbackConfig {
+            enabled: true,
+            endpoint: Some(endpoint),
+            model: Some("test-model".to_string()),
+            ..LlmFallbackConfig::default()
+        };
+
+        let err = call_llm(&config, "test-model", &test_input(), &[]).await.unwrap_err();
+        assert!(err.to_string().contains("429"));
+    }
+
+    #[tokio::test]
+    async fn test_call_llm_refuses_http_under_require_tls() {
+        let (endpoint, requests) = spawn_mock_llm(vec![
+            (200, chat_completion(r#"{"classification": "ALLOW", "reasoning": "Dev command"}"#)),
+        ]);
+        let mut config = LlmFallbackConfig {
+            enabled: true,
+            endpoint: Some(endpoint),
+            model: Some("test-model".to_string()),
+            require_tls: true,
+            ..LlmFallbackConfig::default()
+        };
+
+        let err = call_llm(&config, "test-model", &test_input(), &[]).await.unwrap_err();
+        assert!(err.to_string().contains("require_tls"), "{}", err);
+        assert!(requests.lock().unwrap().is_empty());
+
+        // The mock listens on 127.0.0.1, which allow_localhost_http exempts
+        config.allow_localhost_http = true;
+        assert!(call_llm(&config, "test-model", &test_input(), &[]).await.is_ok());
+    }
+
+    fn verdict(model: &str, classification: &str) -> (String, AssessmentResult) {
+        let verdict = classify(classification, format!("{} says {}", model, classification), vec![
+            format!("{} concern", classification),
+        ])
+        .unwrap();
+        (model.to_string(), AssessmentResult::Assessment(verdict))
+    }
+
+    #[test]
+    fn test_consensus_all_requires_every_model() {
+        let results = vec![verdict("a", "ALLOW"), verdict("b", "QUERY")];
+        let AssessmentResult::Assessment(combined) = combine_verdicts(Consensus::All, results) else {
+            panic!("expected an assessment");
+        };
+        assert_eq!(
+            combined.assessment,
+            SafetyAssessment::Query("1/2 models allowed, 'all' consensus not reached - b (QUERY): b says QUERY".to_string())
+        );
+        assert_eq!(combined.concerns, vec!["ALLOW concern", "QUERY concern"]);
+        let labels: Vec<&str> = combined.model_verdicts.iter().map(|v| v.assessment.as_str()).collect();
+        assert_eq!(labels, ["ALLOW", "QUERY"]);
+    }
+
+    #[test]
+    fn test_consensus_majority() {
+        let results = vec![verdict("a", "ALLOW"), (String::from("b"), AssessmentResult::Timeout), verdict("c", "ALLOW")];
+        let AssessmentResult::Assessment(combined) = combine_verdicts(Consensus::Majority, results) else {
+            panic!("expected an assessment");
+        };
+        assert!(matches!(combined.assessment, SafetyAssessment::Allow(ref r) if r.starts_with("2/3 models allowed")));
+        assert_eq!(combined.model_verdicts[1].assessment, "TIMEOUT");
+
+        // A tie is not a majority
+        let results = vec![verdict("a", "ALLOW"), verdict("b", "QUERY")];
+        let AssessmentResult::Assessment(combined) = combine_verdicts(Consensus::Majority, results) else {
+            panic!("expected an assessment");
+        };
+        assert!(matches!(combined.assessment, SafetyAssessment::Query(_)));
+    }
+
+    #[test]
+    fn test_consensus_without_any_verdict() {
+        let timeouts = vec![("a".to_string(), AssessmentResult::Timeout), ("b".to_string(), AssessmentResult::Timeout)];
+        assert!(matches!(combine_verdicts(Consensus::All, timeouts), AssessmentResult::Timeout));
+
+        let failures = vec![
+            ("a".to_string(), AssessmentResult::Error("boom".to_string())),
+            ("b".to_string(), AssessmentResult::Timeout),
+        ];
+        let AssessmentResult::Error(e) = combine_verdicts(Consensus::All, failures) else {
+            panic!("expected an error");
+        };
+        assert_eq!(e, "All models failed - a: boom; b: timeout");
+    }
+
+    #[tokio::test]
+    async fn test_disagreeing_models_are_queried_and_recorded() {
+        let (endpoint, requests) = spawn_mock_llm(vec![
+            (200, chat_completion(r#"{"classification": "ALLOW", "reasoning": "Dev command"}"#)),
+            (200, chat_completion(r#"{"classification": "QUERY", "reasoning": "Unsure"}"#)),
+        ]);
+        let config = LlmFallbackConfig {
+            enabled: true,
+            endpoint: Some(endpoint),
+            models: vec!["model-a".to_string(), "model-b".to_string()],
+            max_retries: 0,
+            ..LlmFallbackConfig::default()
+        };
+
+        let result = assess_with_llm(&config, &test_input()).await;
+        let (output, metadata) = apply_llm_result(&test_input(), result, "model-a, model-b", false, QueryDecision::Deny);
+        assert_eq!(output.unwrap().hook_specific_output.permission_decision, "deny");
+        assert_eq!(metadata.assessment, "QUERY");
+
+        let models: Vec<&str> = metadata.model_verdicts.iter().map(|v| v.model.as_str()).collect();
+        assert_eq!(models, ["model-a", "model-b"]);
+        let mut labels: Vec<&str> = metadata.model_verdicts.iter().map(|v| v.assessment.as_str()).collect();
+        labels.sort();
+        assert_eq!(labels, ["ALLOW", "QUERY"]);
+
+        let mut asked: Vec<String> = requests
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|body| serde_json::from_str::<serde_json::Value>(body).unwrap()["model"].to_string())
+            .collect();
+        asked.sort();
+        assert_eq!(asked, [r#""model-a""#, r#""model-b""#]);
+    }
+
+    #[tokio::test]
+    async fn test_metadata_records_answering_model() {
+        let routed = serde_json::json!({
+            "model": "anthropic/claude-haiku-4.5-20251001",
+            "provider": "Anthropic",
+            "choices": [{"message": {"content": r#"{"classification": "ALLOW", "reasoning": "Dev command"}"#}}],
+        });
+        let (endpoint, _) = spawn_mock_llm(vec![
+            (200, routed.to_string()),
+            (200, chat_completion(r#"{"classification": "QUERY", "reasoning": "Unsure"}"#)),
+        ]);
+        let config = LlmFallbackConfig {
+            enabled: true,
+            endpoint: Some(endpoint),
+            model: Some("anthropic/claude-haiku-4.5".to_string()),
+            max_retries: 0,
+            ..LlmFallbackConfig::default()
+        };
+        let assess = || async {
+            let result = assess_with_llm(&config, &test_input()).await;
+            apply_llm_result(&test_input(), result, &config.model_label(), false, QueryDecision::Deny).1
+        };
+
+        // The response names the exact model and provider that answered
+        let metadata = assess().await;
+        assert_eq!(metadata.model, "anthropic/claude-haiku-4.5-20251001");
+        assert_eq!(metadata.provider.as_deref(), Some("Anthropic"));
+
+        // Without them, the configured model is recorded
+        let metadata = assess().await;
+        assert_eq!(metadata.model, "anthropic/claude-haiku-4.5");
+        assert_eq!(metadata.provider, None);
+
+        let timeout = apply_llm_result(&test_input(), (AssessmentResult::Timeout, 5), "m", false, QueryDecision::Deny);
+        assert_eq!(timeout.1.model, "m");
+    }
+
+    #[tokio::test]
+    async fn test_record_attempts_keeps_retried_responses() {
+        let responses = || {
+            vec![
+                (200, chat_completion("I cannot decide")),
+                (200, chat_completion(r#"{"classification": "ALLOW", "reasoning": "Dev command"}"#)),
+            ]
+        };
+        let config = |endpoint, record_attempts| LlmFallbackConfig {
+            enabled: true,
+            endpoint: Some(endpoint),
+            model: Some("test-model".to_string()),
+            max_retries: 1,
+            record_attempts,
+            ..LlmFallbackConfig::default()
+        };
+
+        let (endpoint, _) = spawn_mock_llm(responses());
+        let config_on = LlmFallbackConfig { record_prompt: true, ..config(endpoint, true) };
+        let result = assess_with_llm(&config_on, &test_input()).await;
+        let (_, metadata) = apply_llm_result(&test_input(), result, "test-model", false, QueryDecision::Deny);
+        assert_eq!(metadata.assessment, "ALLOW");
+        assert_eq!(metadata.attempts.len(), 2);
+        let (first, second) = (&metadata.attempts[0], &metadata.attempts[1]);
+        assert_eq!((first.attempt, first.content.as_str()), (1, "I cannot decide"));
+        assert_eq!(first.classification, None);
+        assert!(first.error.as_deref().unwrap().contains("No JSON"), "{:?}", first.error);
+        assert_eq!((second.attempt, second.classification.as_deref()), (2, Some("ALLOW")));
+        assert_eq!(second.error, None);
+        assert_eq!(second.model, "test-model");
+        let prompt = metadata.prompt.as_deref().unwrap();
+        assert!(prompt.starts_with("Evaluate this tool use request:"), "{}", prompt);
+
+        // Off by default: the retry still happens but nothing is kept
+        let (endpoint, _) = spawn_mock_llm(responses());
+        let result = assess_with_llm(&config(endpoint, false), &test_input()).await;
+        let (_, metadata) = apply_llm_result(&test_input(), result, "test-model", false, QueryDecision::Deny);
+        assert_eq!(metadata.assessment, "ALLOW");
+        assert!(metadata.attempts.is_empty());
+        assert_eq!(metadata.prompt, None);
+        assert!(!serde_json::to_string(&metadata).unwrap().contains("attempts"));
+    }
+
+    #[test]
+    fn test_safety_prompt_includes_decision_history() {
+        let prompt = build_safety_prompt(&test_input(), &[], &[]);
+        assert!(prompt.starts_with("Evaluate this tool use request:"));
+
+        let history = [HistoryEntry {
+            decision: "deny".to_string(),
+            tool_name: "Bash".to_string(),
+            summary: "rm -rf /".to_string(),
+        }];
+        let prompt = build_safety_prompt(&test_input(), &history, &[]);
+        assert!(prompt.starts_with(
+            "Earlier decisions in this session (oldest first):\n- deny: Bash rm -rf /\n\nEvaluate this tool use request:"
+        ));
+    }
+
+    #[test]
+    fn test_safety_prompt_includes_rule_hints() {
+        let prompt = build_safety_prompt(&test_input(), &[], &[]);
+        assert!(!prompt.contains("Policy notes"));
+        assert!(prompt.contains("}\n\nClassify as ALLOW or QUERY"));
+
+        let hints = ["/srv/data holds customer records".to_string()];
+        let prompt = build_safety_prompt(&test_input(), &[], &hints);
+        assert!(prompt.contains(
+            "}\n\nPolicy notes from the configuration:\n- /srv/data holds customer records\n\nClassify as ALLOW or QUERY"
+        ));
+    }
+
+    fn query_result() -> (AssessmentResult, u64) {
+        let verdict = LlmVerdict {
+            assessment: SafetyAssessment::Query("Deletes files".to_string()),
+            concerns: Vec::new(),
+            model_verdicts: Vec::new(),
+            model: None,
+            provider: None,
+            attempts: Vec::new(),
+            prompt: None,
+        };
+        (AssessmentResult::Assessment(verdict), 5)
+    }
+
+    fn query_output_decision(test_mode: bool, query_decision: QueryDecision) -> Option<String> {
+        let (output, metadata) = apply_llm_result(&test_input(), query_result(), "test-model", test_mode, query_decision);
+        assert_eq!(metadata.assessment, "QUERY");
+        output.map(|o| o.hook_specific_output.permission_decision)
+    }
+
+    #[test]
+    fn test_query_decision_passthrough() {
+        assert_eq!(query_output_decision(false, QueryDecision::Passthrough), None);
+    }
+
+    #[test]
+    fn test_query_decision_ask() {
+        assert_eq!(query_output_decision(false, QueryDecision::Ask).as_deref(), Some("ask"));
+    }
+
+    #[test]
+    fn test_query_decision_deny() {
+        assert_eq!(query_output_decision(false, QueryDecision::Deny).as_deref(), Some("deny"));
+    }
+
+    #[test]
+    fn test_query_decision_ignored_in_test_mode() {
+        assert_eq!(query_output_decision(true, QueryDecision::Ask).as_deref(), Some("deny"));
     }
 }
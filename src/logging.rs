@@ -1,15 +1,23 @@
 #![forbid(unsafe_code)]
 #![warn(clippy::all)]
 
-use crate::config::Rule;
+use crate::config::{LogFormat, Rule};
 use crate::hook_io::HookInput;
+use crate::matcher::{elevation_tool, RuleTraceStep};
 use chrono::{DateTime, Utc};
-use log::warn;
-use nix::fcntl::{Flock, FlockArg};
-use serde::Serialize;
+use log::{debug, warn};
+use nix::fcntl::{Flock, FlockArg, OFlag};
+use serde::{Deserialize, Serialize};
 use std::fs::OpenOptions;
-use std::io::Write;
-use std::path::Path;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::os::unix::fs::{FileTypeExt, OpenOptionsExt};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
 
 // ========== OPERATIONAL LOG (SIMPLIFIED) ==========
 // Purpose: Quick monitoring with minimal overhead
@@ -19,6 +27,9 @@ use std::path::Path;
 struct OperationalLogEntry {
     timestamp: DateTime<Utc>,
     session_id: String,
+    // Shared by every record of one hook run (absent in --deterministic runs)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    trace_id: Option<String>,
     tool_name: String,
     tool_input: serde_json::Value,
     decision: String,          // "allow", "deny", or "passthrough"
@@ -33,6 +44,8 @@ struct OperationalLogEntry {
 struct ReviewLogEntry {
     timestamp: DateTime<Utc>,
     session_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    trace_id: Option<String>,
     tool_name: String,
     tool_input: serde_json::Value,
     cwd: String,
@@ -42,6 +55,10 @@ struct ReviewLogEntry {
     decision_source: String,   // "rule", "llm", or "passthrough"
     reasoning: String,
 
+    // Why the decision passed through to the user (passthroughs only)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    passthrough_reason: Option<PassthroughReason>,
+
     // Rule-based enrichment (if applicable)
     #[serde(skip_serializing_if = "Option::is_none")]
     rule_metadata: Option<RuleMetadata>,
@@ -50,8 +67,136 @@ struct ReviewLogEntry {
     #[serde(skip_serializing_if = "Option::is_none")]
     llm_metadata: Option<LlmMetadata>,
 
+    // Whole hook run up to this entry, when logging.record_latency is on
+    // llm_metadata.processing_time_ms covers only the LLM call
+    #[serde(skip_serializing_if = "Option::is_none")]
+    total_latency_ms: Option<u64>,
+
     // Review flags
     review_flags: ReviewFlags,
+
+    // Evaluation detail for a session's first logging.verbose_first_n operations
+    #[serde(skip_serializing_if = "Option::is_none")]
+    verbose: Option<VerboseDetail>,
+
+    // Times this entry was seen within the de-duplication window
+    #[serde(skip_serializing_if = "is_single")]
+    repeat_count: u64,
+}
+
+fn is_single(count: &u64) -> bool {
+    *count == 1
+}
+
+/// How far back from the end of the review log de-duplication looks for a repeat
+const DEDUP_TAIL_BYTES: u64 = 64 * 1024;
+
+// ========== CSV LOG FORMAT ==========
+// Purpose: Smaller logs for always-on hooks, from the same entry structs
+// Each entry is serialized through serde to JSON, then laid out in fixed columns:
+// string fields as plain text, nested or optional fields as compact JSON
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CellKind {
+    /// Always-present string field, stored as-is
+    Text,
+    /// Any other value, stored as compact JSON; an empty cell means the field was omitted
+    Json,
+}
+
+type LogColumns = &'static [(&'static str, CellKind)];
+
+const OPERATIONAL_COLUMNS: LogColumns = &[
+    ("timestamp", CellKind::Text),
+    ("session_id", CellKind::Text),
+    ("tool_name", CellKind::Text),
+    ("tool_input", CellKind::Json),
+    ("decision", CellKind::Text),
+    ("decision_source", CellKind::Text),
+    ("trace_id", CellKind::Json),
+];
+
+const REVIEW_COLUMNS: LogColumns = &[
+    ("timestamp", CellKind::Text),
+    ("session_id", CellKind::Text),
+    ("tool_name", CellKind::Text),
+    ("tool_input", CellKind::Json),
+    ("cwd", CellKind::Text),
+    ("decision", CellKind::Text),
+    ("decision_source", CellKind::Text),
+    ("reasoning", CellKind::Text),
+    ("passthrough_reason", CellKind::Json),
+    ("rule_metadata", CellKind::Json),
+    ("llm_metadata", CellKind::Json),
+    ("total_latency_ms", CellKind::Json),
+    ("review_flags", CellKind::Json),
+    ("repeat_count", CellKind::Json),
+    ("trace_id", CellKind::Json),
+    ("verbose", CellKind::Json),
+];
+
+fn csv_record(cells: &[String]) -> anyhow::Result<String> {
+    let mut writer = csv::WriterBuilder::new().has_headers(false).from_writer(Vec::new());
+    writer.write_record(cells)?;
+    let bytes = writer.into_inner().map_err(|e| anyhow::anyhow!("CSV writer failed: {}", e))?;
+    Ok(String::from_utf8(bytes)?.trim_end_matches(['\r', '\n']).to_string())
+}
+
+/// Encode an entry as one log record, plus the header to write first into an empty file
+fn encode_entry<T: Serialize>(
+    format: LogFormat,
+    columns: LogColumns,
+    entry: &T,
+) -> anyhow::Result<(String, Option<String>)> {
+    if format == LogFormat::Json {
+        return Ok((serde_json::to_string(entry)?, None));
+    }
+
+    let value = serde_json::to_value(entry)?;
+    let cells: Vec<String> = columns
+        .iter()
+        .map(|(name, kind)| match (value.get(name), kind) {
+            (None | Some(serde_json::Value::Null), _) => String::new(),
+            (Some(serde_json::Value::String(s)), CellKind::Text) => s.clone(),
+            (Some(other), _) => other.to_string(),
+        })
+        .collect();
+    let header: Vec<String> = columns.iter().map(|(name, _)| name.to_string()).collect();
+    Ok((csv_record(&cells)?, Some(csv_record(&header)?)))
+}
+
+/// Convert a CSV operational or review log back to JSON lines, returning the entry count
+/// The header row tells which log it is
+pub fn csv_log_to_json_lines<R: Read, W: Write>(reader: R, mut out: W) -> anyhow::Result<usize> {
+    let mut reader = csv::Reader::from_reader(reader);
+    let headers = reader.headers()?.clone();
+    let columns = [OPERATIONAL_COLUMNS, REVIEW_COLUMNS]
+        .into_iter()
+        .find(|columns| columns.iter().map(|(name, _)| *name).eq(headers.iter()))
+        .ok_or_else(|| {
+            anyhow::anyhow!(
+                "Unrecognized CSV log header '{}' - expected an operational or review log",
+                headers.iter().collect::<Vec<_>>().join(",")
+            )
+        })?;
+
+    let mut count = 0;
+    for record in reader.records() {
+        let record = record?;
+        let mut entry = serde_json::Map::new();
+        for ((name, kind), cell) in columns.iter().zip(record.iter()) {
+            let value = match kind {
+                CellKind::Text => serde_json::Value::String(cell.to_string()),
+                CellKind::Json if cell.is_empty() => continue,
+                CellKind::Json => serde_json::from_str(cell)
+                    .map_err(|e| anyhow::anyhow!("Invalid JSON in column '{}' of entry {}: {}", name, count + 1, e))?,
+            };
+            entry.insert(name.to_string(), value);
+        }
+        writeln!(out, "{}", serde_json::Value::Object(entry))?;
+        count += 1;
+    }
+    Ok(count)
 }
 
 #[derive(Debug, Serialize)]
@@ -64,9 +209,13 @@ pub struct RuleMetadata {
     pub rule_description: Option<String>,
     pub config_file: String,       // Path to config file
     pub matched_pattern: String,   // Which pattern triggered (e.g., "command_regex")
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub review: bool,              // Rule asks for its allows to be reviewed
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub require_audit_flush: bool, // Allow waits for this entry to reach disk
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct LlmMetadata {
     pub assessment: String,        // "ALLOW" or "QUERY"
     pub reasoning: String,
@@ -75,70 +224,421 @@ pub struct LlmMetadata {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub processing_time_ms: Option<u64>,
     pub model: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provider: Option<String>,  // Upstream provider that answered, when the endpoint says
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub concerns: Vec<String>,     // Risk factors the model listed
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub cached: bool,              // Served from the decision cache
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub model_verdicts: Vec<ModelVerdict>, // Each model's own verdict when several were asked
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub attempts: Vec<LlmAttempt>, // Every response parsed, when llm_fallback.record_attempts is on
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompt: Option<String>,    // Prompt sent, for logging.verbose_first_n operations
 }
 
+/// One model's answer in a multi-model consensus
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ModelVerdict {
+    pub model: String,
+    pub assessment: String,        // "ALLOW", "QUERY", "TIMEOUT" or "ERROR"
+    pub reasoning: String,
+}
+
+/// One response from a model and what parsing it gave, retries included
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LlmAttempt {
+    pub model: String,
+    pub attempt: u32,              // 1-based, per model
+    pub content: String,           // Raw message content
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub classification: Option<String>, // "ALLOW" or "QUERY" when the content parsed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,     // Parse failure otherwise
+}
+
+/// How one of a session's first `logging.verbose_first_n` operations was evaluated
+/// The LLM's prompt and responses go in llm_metadata (`prompt` and `attempts`)
 #[derive(Debug, Serialize)]
+pub struct VerboseDetail {
+    pub operation: u64,                 // The session's operation number, 1-based
+    pub deny_rules: Vec<RuleTraceStep>, // Every rule considered, in evaluation order
+    pub allow_rules: Vec<RuleTraceStep>,
+    pub evaluation_ms: u64,             // Rules and LLM, without config loading or logging
+}
+
+/// Why nothing decided a passthrough, so "the LLM was unsure" can be told apart
+/// from "nothing was configured to handle this"
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PassthroughReason {
+    /// No rule matched and the LLM fallback is disabled
+    LlmDisabled,
+    /// No rule matched and the LLM answered QUERY
+    LlmQuery,
+    /// No rule matched and the LLM timed out or failed
+    LlmUnavailable,
+    /// An `on_match = "llm"` rule matched and the LLM answered QUERY
+    EscalatedQuery,
+    /// A `require_llm_concur` allow rule matched but the LLM didn't answer ALLOW
+    LlmNotConcurred,
+}
+
+impl PassthroughReason {
+    /// Cause of a fallback passthrough, from the LLM's assessment
+    pub fn from_llm(llm_metadata: &LlmMetadata) -> Self {
+        match llm_metadata.assessment.as_str() {
+            "QUERY" => PassthroughReason::LlmQuery,
+            _ => PassthroughReason::LlmUnavailable,
+        }
+    }
+
+    pub fn description(self) -> &'static str {
+        match self {
+            PassthroughReason::LlmDisabled => "No rule matched and LLM fallback is disabled",
+            PassthroughReason::LlmQuery => "No rule matched and the LLM was uncertain (QUERY)",
+            PassthroughReason::LlmUnavailable => "No rule matched and the LLM gave no assessment",
+            PassthroughReason::EscalatedQuery => "LLM queried an escalated rule",
+            PassthroughReason::LlmNotConcurred => "LLM did not concur with an allow rule",
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Clone)]
 pub struct ReviewFlags {
     pub needs_review: bool,
     pub risk_level: String,        // "low", "medium", "high"
     pub reasons: Vec<String>,      // Why flagged for review
 }
 
+// ========== DECISION FIFO ==========
+// Purpose: Live feed of decisions for dashboards tailing a named pipe
+
+#[derive(Debug, Serialize)]
+struct DecisionEvent {
+    timestamp: DateTime<Utc>,
+    session_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    trace_id: Option<String>,
+    tool_name: String,
+    decision: String,
+    decision_source: String,
+    reasoning: String,
+}
+
+// ========== CLOCK ==========
+// Purpose: Injectable timestamp source so log output can be made byte-stable
+
+pub trait Clock {
+    fn now(&self) -> DateTime<Utc>;
+}
+
+/// Real wall-clock time (the default)
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+/// Always returns the same instant, for reproducible runs and golden tests
+pub struct FixedClock(pub DateTime<Utc>);
+
+impl Clock for FixedClock {
+    fn now(&self) -> DateTime<Utc> {
+        self.0
+    }
+}
+
+// ========== BUFFERED WRITER ==========
+// Purpose: Batch log lines through a background task so each file is locked once per flush
+
+/// A pending record: target file, encoded line, and the header an empty file needs first
+type PendingLine = (PathBuf, String, Option<String>);
+
+pub struct BufferedLogWriter {
+    sender: mpsc::UnboundedSender<PendingLine>,
+    task: JoinHandle<()>,
+}
+
+impl BufferedLogWriter {
+    /// Start the background writer; it flushes every `flush_interval` or once
+    /// `max_entries` lines are pending, whichever comes first
+    pub fn spawn(flush_interval: Duration, max_entries: usize) -> Self {
+        let (sender, mut receiver) = mpsc::unbounded_channel::<PendingLine>();
+        let task = tokio::spawn(async move {
+            let mut pending = Vec::new();
+            let mut ticker = tokio::time::interval(flush_interval);
+            loop {
+                tokio::select! {
+                    received = receiver.recv() => match received {
+                        Some(line) => {
+                            pending.push(line);
+                            if pending.len() >= max_entries {
+                                flush_pending(&mut pending);
+                            }
+                        }
+                        None => break,
+                    },
+                    _ = ticker.tick() => flush_pending(&mut pending),
+                }
+            }
+            flush_pending(&mut pending);
+        });
+        Self { sender, task }
+    }
+
+    fn send(&self, log_path: &Path, line: String, header: Option<String>) -> anyhow::Result<()> {
+        self.sender
+            .send((log_path.to_path_buf(), line, header))
+            .map_err(|_| anyhow::anyhow!("buffered log writer has stopped"))
+    }
+
+    /// Flush everything still buffered; must be awaited before the process exits
+    pub async fn shutdown(self) {
+        drop(self.sender);
+        if let Err(e) = self.task.await {
+            warn!("Buffered log writer failed: {}", e);
+        }
+    }
+}
+
+/// Write pending lines grouped by file, keeping their order within each file
+fn flush_pending(pending: &mut Vec<PendingLine>) {
+    let mut by_path: Vec<(PathBuf, Vec<String>, Option<String>)> = Vec::new();
+    for (log_path, line, header) in pending.drain(..) {
+        match by_path.iter_mut().find(|(p, _, _)| *p == log_path) {
+            Some((_, lines, _)) => lines.push(line),
+            None => by_path.push((log_path, vec![line], header)),
+        }
+    }
+    for (log_path, lines, header) in by_path {
+        if let Err(e) = append_lines(&log_path, &lines, header.as_deref(), false) {
+            warn!("Failed to flush {} entries to {}: {}", lines.len(), log_path.display(), e);
+        }
+    }
+}
+
 // ========== PUBLIC LOGGING API ==========
 
+/// Where `log_decision` writes, and how
+pub struct LogDestinations<'a> {
+    pub operational_log: &'a Path,
+    pub review_log: &'a Path,
+    pub review_dedup_window_secs: Option<u64>,
+    /// Buffered writer for plain appends; de-duplicated review entries are always written directly
+    pub buffer: Option<&'a BufferedLogWriter>,
+    pub format: LogFormat,
+    /// Write the review entry directly and fsync it, reporting failure to the caller
+    pub durable_review: bool,
+    /// Fraction of allows written to the operational log
+    pub operational_sample_rate: f64,
+    /// Log collector (host:port) that also receives each review entry, and how long to wait on it
+    pub tcp_sink: Option<(&'a str, Duration)>,
+}
+
+impl LogDestinations<'_> {
+    fn append<T: Serialize>(&self, log_path: &Path, columns: LogColumns, entry: &T) -> anyhow::Result<()> {
+        let (line, header) = encode_entry(self.format, columns, entry)?;
+        match self.buffer {
+            Some(buffer) => buffer.send(log_path, line, header),
+            None => append_lines(log_path, &[line], header.as_deref(), false),
+        }
+    }
+
+    fn append_durable<T: Serialize>(&self, log_path: &Path, columns: LogColumns, entry: &T) -> anyhow::Result<()> {
+        let (line, header) = encode_entry(self.format, columns, entry)?;
+        append_lines(log_path, &[line], header.as_deref(), true)
+    }
+}
+
 /// Log a decision to BOTH operational and review logs
+/// Write failures are only warned about, except a durable review write, whose error is returned
+#[allow(clippy::too_many_arguments)]
 pub fn log_decision(
-    operational_log: &Path,
-    review_log: &Path,
+    clock: &dyn Clock,
+    destinations: &LogDestinations<'_>,
     input: &HookInput,
+    trace_id: Option<&str>,
     decision: &str,
     decision_source: &str,
     reasoning: &str,
     rule_metadata: Option<RuleMetadata>,
     llm_metadata: Option<LlmMetadata>,
-) {
-    // Compute review flags
-    let review_flags = compute_review_flags(
-        decision,
-        decision_source,
-        &input.tool_name,
-        &input.tool_input,
-        reasoning,
-        &llm_metadata,
-    );
+    passthrough_reason: Option<PassthroughReason>,
+    total_latency_ms: Option<u64>,
+    review_flags: ReviewFlags,
+    verbose: Option<VerboseDetail>,
+) -> anyhow::Result<()> {
+    // Both entries share one timestamp so they can be correlated
+    let timestamp = clock.now();
 
-    // Log to operational log (simple)
-    let op_entry = OperationalLogEntry {
-        timestamp: Utc::now(),
-        session_id: input.session_id.clone(),
-        tool_name: input.tool_name.clone(),
-        tool_input: input.tool_input.clone(),
-        decision: decision.to_string(),
-        decision_source: decision_source.to_string(),
-    };
-    if let Err(e) = write_log_entry(operational_log, &op_entry) {
-        warn!("Failed to log to operational log: {}", e);
+    // Log to operational log (simple); only allows are ever sampled out
+    if decision != "allow" || sampled_in(input, destinations.operational_sample_rate) {
+        let op_entry = OperationalLogEntry {
+            timestamp,
+            session_id: input.session_id.clone(),
+            trace_id: trace_id.map(str::to_string),
+            tool_name: input.tool_name.clone(),
+            tool_input: input.tool_input.clone(),
+            decision: decision.to_string(),
+            decision_source: decision_source.to_string(),
+        };
+        if let Err(e) = destinations.append(destinations.operational_log, OPERATIONAL_COLUMNS, &op_entry) {
+            warn!("Failed to log to operational log: {}", e);
+        }
     }
 
     // Log to review log (detailed)
     let review_entry = ReviewLogEntry {
-        timestamp: Utc::now(),
+        timestamp,
         session_id: input.session_id.clone(),
+        trace_id: trace_id.map(str::to_string),
         tool_name: input.tool_name.clone(),
         tool_input: input.tool_input.clone(),
         cwd: input.cwd.clone(),
         decision: decision.to_string(),
         decision_source: decision_source.to_string(),
         reasoning: reasoning.to_string(),
+        passthrough_reason,
         rule_metadata,
         llm_metadata,
+        total_latency_ms,
         review_flags,
+        verbose,
+        repeat_count: 1,
+    };
+    if let Some((address, timeout)) = destinations.tcp_sink {
+        match serde_json::to_string(&review_entry) {
+            Ok(line) => send_tcp_line(address, &line, timeout),
+            Err(e) => warn!("Failed to encode review entry for TCP sink: {}", e),
+        }
+    }
+
+    let review_log = destinations.review_log;
+    let durable = destinations.durable_review;
+    let result = match destinations.review_dedup_window_secs {
+        Some(window_secs) => write_review_entry_deduped(review_log, &review_entry, window_secs, durable),
+        None if durable => destinations.append_durable(review_log, REVIEW_COLUMNS, &review_entry),
+        None => destinations.append(review_log, REVIEW_COLUMNS, &review_entry),
+    };
+    match result {
+        Err(e) if durable => Err(e.context(format!("Failed to durably write review log {}", review_log.display()))),
+        Err(e) => {
+            warn!("Failed to log to review log: {}", e);
+            Ok(())
+        }
+        Ok(()) => Ok(()),
+    }
+}
+
+/// Whether an input falls inside the sample, by a hash of its tool name and tool_input,
+/// so the same operation is always either logged or skipped
+fn sampled_in(input: &HookInput, sample_rate: f64) -> bool {
+    if sample_rate >= 1.0 {
+        return true;
+    }
+    let mut hasher = DefaultHasher::new();
+    input.tool_name.hash(&mut hasher);
+    input.tool_input.to_string().hash(&mut hasher);
+    (hasher.finish() as f64 / u64::MAX as f64) < sample_rate
+}
+
+// ========== TCP SINK ==========
+// Purpose: Ship review entries to a log collector (e.g. Fluentd's in_tcp) as NDJSON
+// One connection per process is kept open and reused, and replaced when a write fails
+
+static TCP_SINK: Mutex<Option<(String, TcpStream)>> = Mutex::new(None);
+
+/// Send one JSON line to `address` without holding up the hook for longer than `timeout`
+/// per attempt; a failed write is retried once on a fresh connection, then the line is dropped
+pub fn send_tcp_line(address: &str, line: &str, timeout: Duration) {
+    let mut pooled = TCP_SINK.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    let payload = format!("{}\n", line);
+    for attempt in 0..2 {
+        let mut stream = match pooled.take() {
+            Some((pooled_address, stream)) if pooled_address == address => stream,
+            _ => match connect_tcp_sink(address, timeout) {
+                Ok(stream) => stream,
+                Err(e) => {
+                    debug!("Skipped TCP sink {}: {}", address, e);
+                    return;
+                }
+            },
+        };
+        match stream.write_all(payload.as_bytes()) {
+            Ok(()) => {
+                *pooled = Some((address.to_string(), stream));
+                return;
+            }
+            Err(e) => debug!("TCP sink {} write failed (attempt {}): {}", address, attempt + 1, e),
+        }
+    }
+}
+
+fn connect_tcp_sink(address: &str, timeout: Duration) -> anyhow::Result<TcpStream> {
+    let socket_address = address
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("'{}' resolved to no addresses", address))?;
+    let stream = TcpStream::connect_timeout(&socket_address, timeout)?;
+    stream.set_write_timeout(Some(timeout))?;
+    Ok(stream)
+}
+
+/// A random (version 4) UUID identifying one hook run in every log and sink it writes to
+pub fn new_trace_id() -> String {
+    let bits = (rand::random::<u128>() & !(0xf000 << 64) & !(0xc << 60)) | (0x4000 << 64) | (0x8 << 60);
+    let hex = format!("{:032x}", bits);
+    format!("{}-{}-{}-{}-{}", &hex[..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..])
+}
+
+/// Write a decision event to a FIFO without ever blocking the hook
+/// A missing FIFO, no reader, or a full pipe just drops the event
+pub fn write_decision_fifo(
+    clock: &dyn Clock,
+    fifo: &Path,
+    input: &HookInput,
+    trace_id: Option<&str>,
+    decision: &str,
+    decision_source: &str,
+    reasoning: &str,
+) {
+    let event = DecisionEvent {
+        timestamp: clock.now(),
+        session_id: input.session_id.clone(),
+        trace_id: trace_id.map(str::to_string),
+        tool_name: input.tool_name.clone(),
+        decision: decision.to_string(),
+        decision_source: decision_source.to_string(),
+        reasoning: reasoning.to_string(),
     };
-    if let Err(e) = write_log_entry(review_log, &review_entry) {
-        warn!("Failed to log to review log: {}", e);
+    if let Err(e) = try_write_fifo(fifo, &event) {
+        debug!("Skipped decision FIFO {}: {}", fifo.display(), e);
     }
 }
 
+fn try_write_fifo(fifo: &Path, event: &DecisionEvent) -> anyhow::Result<()> {
+    let json_line = format!("{}\n", serde_json::to_string(event)?);
+
+    // Never write into a regular file that happens to sit at the configured path
+    if !std::fs::metadata(fifo)?.file_type().is_fifo() {
+        anyhow::bail!("not a FIFO");
+    }
+
+    // O_NONBLOCK makes open fail with ENXIO when nobody is reading
+    let mut file = OpenOptions::new()
+        .write(true)
+        .custom_flags(OFlag::O_NONBLOCK.bits())
+        .open(fifo)?;
+
+    // Single write so the line is atomic (well under PIPE_BUF)
+    file.write_all(json_line.as_bytes())?;
+    Ok(())
+}
+
 /// Helper to create RuleMetadata from a matched rule
 pub fn create_rule_metadata(
     rule: &Rule,
@@ -155,6 +655,8 @@ pub fn create_rule_metadata(
         rule_description: rule.description.clone(),
         config_file: config_path.display().to_string(),
         matched_pattern: matched_pattern.to_string(),
+        review: rule.review,
+        require_audit_flush: rule.require_audit_flush,
     }
 }
 
@@ -165,6 +667,7 @@ pub fn create_llm_metadata(
     model: &str,
     processing_time_ms: Option<u64>,
     confidence: Option<String>,
+    concerns: Vec<String>,
 ) -> LlmMetadata {
     LlmMetadata {
         assessment: assessment.to_string(),
@@ -172,24 +675,49 @@ pub fn create_llm_metadata(
         confidence,
         processing_time_ms,
         model: model.to_string(),
+        provider: None,
+        concerns,
+        cached: false,
+        model_verdicts: Vec::new(),
+        attempts: Vec::new(),
+        prompt: None,
     }
 }
 
-// ========== INTERNAL HELPERS ==========
+/// Rank a risk level for threshold comparisons ("low" < "medium" < "high")
+pub fn risk_level_rank(level: &str) -> Option<u8> {
+    match level {
+        "low" => Some(0),
+        "medium" => Some(1),
+        "high" => Some(2),
+        _ => None,
+    }
+}
 
 /// Compute review flags based on decision context
-fn compute_review_flags(
+pub fn compute_review_flags(
     decision: &str,
     decision_source: &str,
     tool_name: &str,
     tool_input: &serde_json::Value,
     reasoning: &str,
+    rule_metadata: &Option<RuleMetadata>,
     _llm_metadata: &Option<LlmMetadata>,
 ) -> ReviewFlags {
     let mut needs_review = false;
     let mut reasons = Vec::new();
     let mut risk_level = "low".to_string();
 
+    // Rule allows are pre-approved unless the rule opts in to review
+    if decision == "allow"
+        && decision_source == "rule"
+        && let Some(rule) = rule_metadata
+        && rule.review
+    {
+        needs_review = true;
+        reasons.push(format!("Allowed by rule '{}' marked for review", rule.rule_id));
+    }
+
     // Flag LLM allows for risky patterns
     if decision == "allow" && decision_source == "llm" {
         let input_str = tool_input.to_string().to_lowercase();
@@ -207,10 +735,12 @@ fn compute_review_flags(
                 risk_level = "high".to_string();
                 reasons.push("LLM allowed piped shell execution".to_string());
             }
-            if input_str.contains("sudo") {
+            if let Some(command) = tool_input.get("command").and_then(|c| c.as_str())
+                && let Some(tool) = elevation_tool(command)
+            {
                 needs_review = true;
                 risk_level = "high".to_string();
-                reasons.push("LLM allowed sudo command".to_string());
+                reasons.push(format!("LLM allowed {} command", tool));
             }
         }
 
@@ -252,10 +782,41 @@ fn compute_review_flags(
     }
 }
 
-/// Generic log writer with file locking
-fn write_log_entry<T: Serialize>(log_path: &Path, entry: &T) -> anyhow::Result<()> {
-    let json_line = serde_json::to_string(entry)?;
+/// Flag an input whose rule matching ran past the deadline, since the skipped
+/// rules might have denied it
+pub fn flag_match_timeouts(flags: &mut ReviewFlags, timed_out_rules: &[String]) {
+    if timed_out_rules.is_empty() {
+        return;
+    }
+    flags.needs_review = true;
+    if flags.risk_level == "low" {
+        flags.risk_level = "medium".to_string();
+    }
+    flags.reasons.push(format!(
+        "Rule matching hit the deadline - skipped rules: {}",
+        timed_out_rules.join(", ")
+    ));
+}
+
+/// Flag an input matched by rules from `enforce = false` sections, so audit-only
+/// rules can be vetted against real traffic before they are enforced
+pub fn flag_audit_matches(flags: &mut ReviewFlags, audit_matches: &[String]) {
+    if audit_matches.is_empty() {
+        return;
+    }
+    flags.needs_review = true;
+    flags.reasons.push(format!(
+        "Audit-only rules matched (not enforced): {}",
+        audit_matches.join(", ")
+    ));
+}
+
+// ========== INTERNAL HELPERS ==========
 
+/// Generic log writer with file locking
+/// Append log lines under a single exclusive lock, starting an empty file with `header`
+/// With `sync`, returns only once the lines have been flushed to disk
+fn append_lines(log_path: &Path, lines: &[String], header: Option<&str>, sync: bool) -> anyhow::Result<()> {
     let file = OpenOptions::new()
         .create(true)
         .append(true)
@@ -263,10 +824,478 @@ fn write_log_entry<T: Serialize>(log_path: &Path, entry: &T) -> anyhow::Result<(
 
     let mut flock = Flock::lock(file, FlockArg::LockExclusive).map_err(|(_, e)| e)?;
 
-    writeln!(flock, "{}", json_line)?;
+    if let Some(header) = header
+        && flock.metadata()?.len() == 0
+    {
+        writeln!(flock, "{}", header)?;
+    }
+
+    for json_line in lines {
+        writeln!(flock, "{}", json_line)?;
+    }
+    if sync {
+        flock.sync_all()?;
+    }
+
+    flock.unlock().map_err(|(_, e)| e)?;
+
+    Ok(())
+}
+
+/// Append a review entry, or bump `repeat_count` on a recent identical one
+/// Identical means same session, tool, tool_input and decision, first logged within the window
+fn write_review_entry_deduped(
+    log_path: &Path,
+    entry: &ReviewLogEntry,
+    window_secs: u64,
+    sync: bool,
+) -> anyhow::Result<()> {
+    let file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .read(true)
+        .write(true)
+        .open(log_path)?;
+
+    let mut flock = Flock::lock(file, FlockArg::LockExclusive).map_err(|(_, e)| e)?;
+
+    let len = flock.metadata()?.len();
+    let tail_start = len.saturating_sub(DEDUP_TAIL_BYTES);
+    flock.seek(SeekFrom::Start(tail_start))?;
+    let mut tail = Vec::new();
+    flock.read_to_end(&mut tail)?;
+
+    // Line start offsets within the tail, skipping a partial first line
+    let mut line_starts: Vec<usize> = if tail_start == 0 { vec![0] } else { Vec::new() };
+    line_starts.extend(
+        tail.iter()
+            .enumerate()
+            .filter(|(idx, byte)| **byte == b'\n' && idx + 1 < tail.len())
+            .map(|(idx, _)| idx + 1),
+    );
+
+    let window_start = entry.timestamp - chrono::Duration::seconds(window_secs as i64);
+    let mut repeat = None;
+    for &start in line_starts.iter().rev() {
+        let end = tail[start..].iter().position(|b| *b == b'\n').map_or(tail.len(), |p| start + p);
+        let Ok(previous) = serde_json::from_slice::<serde_json::Value>(&tail[start..end]) else {
+            continue;
+        };
+        let logged_at = previous["timestamp"]
+            .as_str()
+            .and_then(|t| DateTime::parse_from_rfc3339(t).ok());
+        if logged_at.is_none_or(|t| t < window_start) {
+            break;
+        }
+        if previous["session_id"] == entry.session_id.as_str()
+            && previous["tool_name"] == entry.tool_name.as_str()
+            && previous["tool_input"] == entry.tool_input
+            && previous["decision"] == entry.decision.as_str()
+        {
+            repeat = Some((start, end, previous));
+            break;
+        }
+    }
+
+    match repeat {
+        Some((start, end, mut previous)) => {
+            let count = previous["repeat_count"].as_u64().unwrap_or(1);
+            previous["repeat_count"] = serde_json::json!(count + 1);
+
+            // Rewrite the repeated line in place, keeping every later line after it
+            let mut rewritten = serde_json::to_vec(&previous)?;
+            rewritten.extend_from_slice(&tail[end..]);
+            let offset = tail_start + start as u64;
+            flock.set_len(offset)?;
+            flock.seek(SeekFrom::Start(offset))?;
+            flock.write_all(&rewritten)?;
+        }
+        None => {
+            flock.seek(SeekFrom::End(0))?;
+            writeln!(flock, "{}", serde_json::to_string(entry)?)?;
+        }
+    }
+    if sync {
+        flock.sync_all()?;
+    }
 
     flock.unlock().map_err(|(_, e)| e)?;
 
     Ok(())
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_input() -> HookInput {
+        HookInput {
+            session_id: "fifo-session".to_string(),
+            transcript_path: "/tmp/test".to_string(),
+            cwd: "/home/user/project".to_string(),
+            hook_event_name: "PreToolUse".to_string(),
+            tool_name: "Bash".to_string(),
+            tool_input: serde_json::json!({"command": "rm -rf /"}),
+            extra: serde_json::Map::new(),
+            input_root: None,
+            path_style: Default::default(),
+        }
+    }
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("hook-logging-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir.join(format!("{}-{}", name, rand::random::<u64>()))
+    }
+
+    #[test]
+    fn test_write_decision_fifo_with_reader() {
+        let fifo = temp_path("decisions.fifo");
+        nix::unistd::mkfifo(&fifo, nix::sys::stat::Mode::S_IRWXU).unwrap();
+
+        let mut reader = OpenOptions::new()
+            .read(true)
+            .custom_flags(OFlag::O_NONBLOCK.bits())
+            .open(&fifo)
+            .unwrap();
+
+        write_decision_fifo(&SystemClock, &fifo, &test_input(), None, "deny", "rule", "Bash, command: rm -rf /");
+
+        let mut received = String::new();
+        reader.read_to_string(&mut received).unwrap();
+        let event: serde_json::Value = serde_json::from_str(received.trim()).unwrap();
+        assert_eq!(event["session_id"], "fifo-session");
+        assert_eq!(event["decision"], "deny");
+        assert_eq!(event["decision_source"], "rule");
+    }
+
+    #[test]
+    fn test_write_decision_fifo_without_reader_does_not_block() {
+        let fifo = temp_path("unread.fifo");
+        nix::unistd::mkfifo(&fifo, nix::sys::stat::Mode::S_IRWXU).unwrap();
+        write_decision_fifo(&SystemClock, &fifo, &test_input(), None, "deny", "rule", "no reader");
+
+        let missing = temp_path("missing.fifo");
+        write_decision_fifo(&SystemClock, &missing, &test_input(), None, "deny", "rule", "no fifo");
+    }
+
+    #[test]
+    fn test_review_log_golden_line() {
+        let operational_log = temp_path("operational.log");
+        let review_log = temp_path("review.log");
+        let clock = FixedClock(DateTime::UNIX_EPOCH);
+        let input = test_input();
+        let rule_metadata = RuleMetadata {
+            rule_id: "deny-rm".to_string(),
+            section_name: "security".to_string(),
+            rule_type: "deny".to_string(),
+            rule_index: 0,
+            rule_description: None,
+            config_file: "example.toml".to_string(),
+            matched_pattern: "command_regex '^rm '".to_string(),
+            review: false,
+            require_audit_flush: false,
+        };
+        let review_flags =
+            compute_review_flags("deny", "rule", &input.tool_name, &input.tool_input, "Bash, command: rm -rf /", &None, &None);
+
+        let destinations = LogDestinations {
+            operational_log: &operational_log,
+            review_log: &review_log,
+            review_dedup_window_secs: None,
+            buffer: None,
+            format: LogFormat::Json,
+            durable_review: false,
+            operational_sample_rate: 1.0,
+            tcp_sink: None,
+        };
+        log_decision(
+            &clock,
+            &destinations,
+            &input,
+            None,
+            "deny",
+            "rule",
+            "Bash, command: rm -rf /",
+            Some(rule_metadata),
+            None,
+            None,
+            None,
+            review_flags,
+            None,
+        )
+        .unwrap();
+
+        let review_line = std::fs::read_to_string(&review_log).unwrap();
+        assert_eq!(
+            review_line,
+            concat!(
+                r#"{"timestamp":"1970-01-01T00:00:00Z","session_id":"fifo-session","tool_name":"Bash","#,
+                r#""tool_input":{"command":"rm -rf /"},"cwd":"/home/user/project","decision":"deny","#,
+                r#""decision_source":"rule","reasoning":"Bash, command: rm -rf /","#,
+                r#""rule_metadata":{"rule_id":"deny-rm","section_name":"security","rule_type":"deny","#,
+                r#""rule_index":0,"config_file":"example.toml","matched_pattern":"command_regex '^rm '"},"#,
+                r#""review_flags":{"needs_review":false,"risk_level":"low","reasons":[]}}"#,
+                "\n"
+            )
+        );
+
+        let operational_line = std::fs::read_to_string(&operational_log).unwrap();
+        assert!(operational_line.starts_with(r#"{"timestamp":"1970-01-01T00:00:00Z""#));
+    }
+
+    #[test]
+    fn test_csv_logs_round_trip_to_json() {
+        let input = test_input();
+        let rule_metadata = || RuleMetadata {
+            rule_id: "deny-rm".to_string(),
+            section_name: "security".to_string(),
+            rule_type: "deny".to_string(),
+            rule_index: 0,
+            rule_description: Some("Block \"rm\", always".to_string()),
+            config_file: "example.toml".to_string(),
+            matched_pattern: "command_regex '^rm '".to_string(),
+            review: false,
+            require_audit_flush: false,
+        };
+        let llm_metadata = create_llm_metadata("QUERY", "multi\nline, reasoning", "m", Some(3), None, Vec::new());
+
+        // Log the same decisions in both formats and compare entry by entry
+        let log_both = |format: LogFormat| {
+            let operational_log = temp_path("round-trip-operational.log");
+            let review_log = temp_path("round-trip-review.log");
+            let destinations = LogDestinations {
+                operational_log: &operational_log,
+                review_log: &review_log,
+                review_dedup_window_secs: None,
+                buffer: None,
+                format,
+                durable_review: false,
+            operational_sample_rate: 1.0,
+                tcp_sink: None,
+            };
+            let flags = compute_review_flags("deny", "rule", &input.tool_name, &input.tool_input, "r", &None, &None);
+            let clock = FixedClock(DateTime::UNIX_EPOCH);
+            log_decision(&clock, &destinations, &input, None, "deny", "rule", "r", Some(rule_metadata()), None, None, None, flags.clone(), None).unwrap();
+            let reason = Some(PassthroughReason::LlmQuery);
+            log_decision(&clock, &destinations, &input, None, "passthrough", "passthrough", "", None, Some(llm_metadata.clone()), reason, Some(12), flags, None).unwrap();
+            (operational_log, review_log)
+        };
+        let json_logs = log_both(LogFormat::Json);
+        let csv_logs = log_both(LogFormat::Csv);
+
+        let csv_review = std::fs::read_to_string(&csv_logs.1).unwrap();
+        assert!(csv_review.starts_with("timestamp,session_id,tool_name,tool_input,cwd,"));
+        let json_review = std::fs::read_to_string(&json_logs.1).unwrap();
+        assert!(json_review.lines().nth(1).unwrap().contains(r#""passthrough_reason":"llm_query""#));
+
+        let parse = |text: &str| -> Vec<serde_json::Value> {
+            text.lines().map(|line| serde_json::from_str(line).unwrap()).collect()
+        };
+        for (json_log, csv_log) in [(&json_logs.0, &csv_logs.0), (&json_logs.1, &csv_logs.1)] {
+            let mut converted = Vec::new();
+            let count = csv_log_to_json_lines(std::fs::File::open(csv_log).unwrap(), &mut converted).unwrap();
+            assert_eq!(count, 2);
+            assert_eq!(
+                parse(&String::from_utf8(converted).unwrap()),
+                parse(&std::fs::read_to_string(json_log).unwrap())
+            );
+        }
+    }
+
+    #[test]
+    fn test_csv_reader_rejects_unknown_header() {
+        let err = csv_log_to_json_lines("a,b\n1,2\n".as_bytes(), Vec::new()).unwrap_err();
+        assert!(err.to_string().contains("Unrecognized CSV log header 'a,b'"));
+    }
+
+    #[test]
+    fn test_review_log_dedup_collapses_repeats() {
+        let operational_log = temp_path("dedup-operational.log");
+        let review_log = temp_path("dedup-review.log");
+        let start = DateTime::UNIX_EPOCH;
+        let log_at = |secs: i64, input: &HookInput| {
+            let review_flags = compute_review_flags("deny", "rule", &input.tool_name, &input.tool_input, "rm", &None, &None);
+            let destinations = LogDestinations {
+                operational_log: &operational_log,
+                review_log: &review_log,
+                review_dedup_window_secs: Some(60),
+                buffer: None,
+                format: LogFormat::Json,
+                durable_review: false,
+            operational_sample_rate: 1.0,
+                tcp_sink: None,
+            };
+            log_decision(
+                &FixedClock(start + chrono::Duration::seconds(secs)),
+                &destinations,
+                input,
+                None,
+                "deny",
+                "rule",
+                "rm",
+                None,
+                None,
+                None,
+                None,
+                review_flags,
+                None,
+            )
+            .unwrap();
+        };
+
+        let input = test_input();
+        let mut other = test_input();
+        other.tool_input = serde_json::json!({"command": "rm -rf ~"});
+
+        log_at(0, &input);
+        log_at(5, &other);
+        log_at(10, &input);
+        log_at(20, &input);
+        // Outside the window of the first entry, so it starts a new one
+        log_at(90, &input);
+
+        let lines: Vec<serde_json::Value> = std::fs::read_to_string(&review_log)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0]["repeat_count"], 3);
+        assert_eq!(lines[1]["tool_input"]["command"], "rm -rf ~");
+        assert!(lines[1].get("repeat_count").is_none());
+        assert!(lines[2].get("repeat_count").is_none());
+
+        // The operational log is never de-duplicated
+        assert_eq!(std::fs::read_to_string(&operational_log).unwrap().lines().count(), 5);
+    }
+
+    #[test]
+    fn test_operational_sampling_never_drops_denies() {
+        let operational_log = temp_path("sampled-operational.log");
+        let review_log = temp_path("sampled-review.log");
+        let destinations = LogDestinations {
+            operational_log: &operational_log,
+            review_log: &review_log,
+            review_dedup_window_secs: None,
+            buffer: None,
+            format: LogFormat::Json,
+            durable_review: false,
+            operational_sample_rate: 0.0,
+            tcp_sink: None,
+        };
+        let input = test_input();
+        for decision in ["deny", "allow", "passthrough", "ask", "allow"] {
+            let flags = compute_review_flags(decision, "rule", &input.tool_name, &input.tool_input, "r", &None, &None);
+            log_decision(&SystemClock, &destinations, &input, None, decision, "rule", "r", None, None, None, None, flags, None).unwrap();
+        }
+
+        let decisions: Vec<String> = std::fs::read_to_string(&operational_log)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap()["decision"].as_str().unwrap().to_string())
+            .collect();
+        assert_eq!(decisions, ["deny", "passthrough", "ask"]);
+        // The review log is never sampled
+        assert_eq!(std::fs::read_to_string(&review_log).unwrap().lines().count(), 5);
+    }
+
+    #[test]
+    fn test_tcp_sink_receives_review_entries() {
+        use std::io::BufRead;
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let review_log = temp_path("tcp-review.log");
+        let destinations = LogDestinations {
+            operational_log: &temp_path("tcp-operational.log"),
+            review_log: &review_log,
+            review_dedup_window_secs: None,
+            buffer: None,
+            format: LogFormat::Csv,
+            durable_review: false,
+            operational_sample_rate: 1.0,
+            tcp_sink: Some((&address, Duration::from_secs(2))),
+        };
+        let input = test_input();
+        for decision in ["deny", "allow"] {
+            let flags = compute_review_flags(decision, "rule", &input.tool_name, &input.tool_input, "r", &None, &None);
+            log_decision(&SystemClock, &destinations, &input, Some("trace-1"), decision, "rule", "r", None, None, None, None, flags, None)
+                .unwrap();
+        }
+
+        // Both entries arrive as JSON lines, whatever the file format, over one pooled connection
+        let (stream, _) = listener.accept().unwrap();
+        let mut lines = std::io::BufReader::new(stream).lines();
+        for decision in ["deny", "allow"] {
+            let entry: serde_json::Value = serde_json::from_str(&lines.next().unwrap().unwrap()).unwrap();
+            assert_eq!(entry["decision"], decision);
+            assert_eq!(entry["session_id"], "fifo-session");
+            assert_eq!(entry["trace_id"], "trace-1");
+        }
+        assert_eq!(std::fs::read_to_string(&review_log).unwrap().lines().count(), 3);
+
+        // Nothing listening: the line is dropped and logging still succeeds
+        drop(lines);
+        drop(listener);
+        let flags = compute_review_flags("deny", "rule", &input.tool_name, &input.tool_input, "r", &None, &None);
+        assert!(log_decision(&SystemClock, &destinations, &input, None, "deny", "rule", "r", None, None, None, None, flags, None).is_ok());
+    }
+
+    #[test]
+    fn test_sampling_is_consistent_per_input() {
+        let inputs: Vec<HookInput> = (0..200)
+            .map(|i| HookInput {
+                tool_input: serde_json::json!({"command": format!("cargo test case_{}", i)}),
+                ..test_input()
+            })
+            .collect();
+        let sampled: Vec<bool> = inputs.iter().map(|input| sampled_in(input, 0.5)).collect();
+        let again: Vec<bool> = inputs.iter().map(|input| sampled_in(input, 0.5)).collect();
+        assert_eq!(sampled, again);
+
+        let kept = sampled.iter().filter(|kept| **kept).count();
+        assert!((60..140).contains(&kept), "{}", kept);
+        assert!(inputs.iter().all(|input| sampled_in(input, 1.0)));
+        assert!(!inputs.iter().any(|input| sampled_in(input, 0.0)));
+    }
+
+    #[tokio::test]
+    async fn test_buffered_writer_persists_all_entries() {
+        let operational_log = temp_path("buffered-operational.log");
+        let review_log = temp_path("buffered-review.log");
+        let writer = BufferedLogWriter::spawn(Duration::from_secs(3600), 4);
+        let destinations = LogDestinations {
+            operational_log: &operational_log,
+            review_log: &review_log,
+            review_dedup_window_secs: None,
+            buffer: Some(&writer),
+            format: LogFormat::Json,
+            durable_review: false,
+            operational_sample_rate: 1.0,
+            tcp_sink: None,
+        };
+
+        let input = test_input();
+        for _ in 0..10 {
+            let review_flags = compute_review_flags("deny", "rule", &input.tool_name, &input.tool_input, "rm", &None, &None);
+            log_decision(&SystemClock, &destinations, &input, None, "deny", "rule", "rm", None, None, None, None, review_flags, None).unwrap();
+        }
+        writer.shutdown().await;
+
+        let count_lines = |path: &Path| std::fs::read_to_string(path).unwrap().lines().count();
+        assert_eq!(count_lines(&operational_log), 10);
+        assert_eq!(count_lines(&review_log), 10);
+    }
+
+    #[tokio::test]
+    async fn test_buffered_writer_flushes_on_interval() {
+        let log = temp_path("buffered-interval.log");
+        let writer = BufferedLogWriter::spawn(Duration::from_millis(20), 1000);
+        writer.send(&log, "{\"n\":1}".to_string(), None).unwrap();
+
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(std::fs::read_to_string(&log).unwrap(), "{\"n\":1}\n");
+        writer.shutdown().await;
+    }
+}
@@ -0,0 +1,175 @@
+#![forbid(unsafe_code)]
+#![warn(clippy::all)]
+
+use crate::config::CONFIG_SCHEMA_VERSION;
+use anyhow::{Context, Result};
+use toml::{Table, Value};
+
+/// Section that receives the rules of a flat (schema version 1) config
+const MIGRATED_SECTION: &str = "migrated";
+
+/// Schema version of a parsed config table
+/// Configs without `schema_version` are version 1 if they still use top-level
+/// `[[allow]]` / `[[deny]]` arrays, and the current version otherwise
+pub fn detect_schema_version(table: &Table) -> Result<u32> {
+    match table.get("schema_version") {
+        Some(Value::Integer(version)) => u32::try_from(*version)
+            .ok()
+            .filter(|v| *v >= 1)
+            .with_context(|| format!("Invalid schema_version {}", version)),
+        Some(other) => anyhow::bail!("schema_version must be an integer, got {}", other),
+        None if is_flat(table) => Ok(1),
+        None => Ok(CONFIG_SCHEMA_VERSION),
+    }
+}
+
+fn is_flat(table: &Table) -> bool {
+    ["allow", "deny"]
+        .iter()
+        .any(|key| matches!(table.get(*key), Some(Value::Array(_))))
+}
+
+/// Upgrade a config table to the current schema version, one version step at a time
+pub fn migrate(mut table: Table) -> Result<Table> {
+    let mut version = detect_schema_version(&table)?;
+    if version > CONFIG_SCHEMA_VERSION {
+        anyhow::bail!(
+            "Config schema_version {} is newer than this hook supports ({})",
+            version,
+            CONFIG_SCHEMA_VERSION
+        );
+    }
+
+    while version < CONFIG_SCHEMA_VERSION {
+        table = match version {
+            1 => migrate_v1_to_v2(table)?,
+            _ => unreachable!("no migration from schema version {}", version),
+        };
+        version += 1;
+    }
+
+    table.insert("schema_version".to_string(), Value::Integer(CONFIG_SCHEMA_VERSION as i64));
+    Ok(table)
+}
+
+/// Version 1 kept rules in top-level `[[allow]]` / `[[deny]]` arrays, often without ids
+/// Version 2 groups rules into named sections and requires a unique id per rule
+fn migrate_v1_to_v2(mut table: Table) -> Result<Table> {
+    if table.contains_key(MIGRATED_SECTION) {
+        anyhow::bail!(
+            "Cannot migrate: a '{}' section already exists - rename it first",
+            MIGRATED_SECTION
+        );
+    }
+
+    let mut section = Table::new();
+    section.insert(
+        "description".to_string(),
+        Value::String("Rules migrated from a flat (schema version 1) config".to_string()),
+    );
+    section.insert("priority".to_string(), Value::Integer(50));
+
+    for kind in ["deny", "allow"] {
+        let Some(rules) = table.remove(kind) else {
+            continue;
+        };
+        let Value::Array(rules) = rules else {
+            anyhow::bail!("Top-level '{}' must be an array of rules", kind);
+        };
+
+        let mut migrated = Vec::new();
+        for (idx, rule) in rules.into_iter().enumerate() {
+            let Value::Table(mut rule) = rule else {
+                anyhow::bail!("{}[{}] must be a table", kind, idx);
+            };
+            rule.entry("id")
+                .or_insert_with(|| Value::String(format!("{}-{}-{}", MIGRATED_SECTION, kind, idx + 1)));
+            migrated.push(Value::Table(rule));
+        }
+        section.insert(kind.to_string(), Value::Array(migrated));
+    }
+
+    table.insert(MIGRATED_SECTION.to_string(), Value::Table(section));
+    Ok(table)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_migrate_flat_config() -> Result<()> {
+        let old: Table = toml::from_str(
+            r#"
+[logging]
+log_level = "debug"
+
+[[allow]]
+tool = "Read"
+file_path_regex = "^/safe/"
+
+[[allow]]
+id = "allow-cargo"
+tool = "Bash"
+command_regex = "^cargo "
+
+[[deny]]
+tool = "Read"
+file_path_regex = "\\.env$"
+"#,
+        )?;
+        assert_eq!(detect_schema_version(&old)?, 1);
+
+        let expected: Table = toml::from_str(
+            r#"
+schema_version = 2
+
+[logging]
+log_level = "debug"
+
+[migrated]
+description = "Rules migrated from a flat (schema version 1) config"
+priority = 50
+
+[[migrated.allow]]
+id = "migrated-allow-1"
+tool = "Read"
+file_path_regex = "^/safe/"
+
+[[migrated.allow]]
+id = "allow-cargo"
+tool = "Bash"
+command_regex = "^cargo "
+
+[[migrated.deny]]
+id = "migrated-deny-1"
+tool = "Read"
+file_path_regex = "\\.env$"
+"#,
+        )?;
+        let migrated = migrate(old)?;
+        assert_eq!(migrated, expected);
+
+        // The result loads as a current config
+        let config: crate::config::Config = toml::from_str(&toml::to_string(&migrated)?)?;
+        assert_eq!(config.sections[MIGRATED_SECTION].allow.len(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_migrate_current_config_only_stamps_version() -> Result<()> {
+        let current: Table = toml::from_str("[build]\n[[build.allow]]\nid = \"a\"\ntool = \"Read\"\n")?;
+        let migrated = migrate(current.clone())?;
+        assert_eq!(migrated["schema_version"], Value::Integer(CONFIG_SCHEMA_VERSION as i64));
+        assert_eq!(migrated["build"], current["build"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_migrate_rejects_newer_schema() {
+        let future: Table = toml::from_str("schema_version = 99\n").unwrap();
+        let err = migrate(future).unwrap_err();
+        assert!(err.to_string().contains("newer than this hook supports"));
+    }
+}
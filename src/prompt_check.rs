@@ -0,0 +1,181 @@
+#![forbid(unsafe_code)]
+#![warn(clippy::all)]
+
+use crate::hook_io::HookInput;
+use crate::llm_safety::{self, AssessmentResult, SafetyAssessment, SafetyClassifier};
+use std::io::{self, Write};
+
+// ========== PROMPT SELF-CHECK ==========
+// Purpose: Ask the configured model about the labeled examples in its own system prompt,
+// as a quick sanity check of the model + prompt combination
+
+/// One `- Tool argument → LABEL: why` line of the system prompt's examples
+#[derive(Debug, Clone)]
+pub struct PromptExample {
+    /// The example as written, before the arrow
+    pub text: String,
+    /// SAFE, UNSAFE or UNKNOWN
+    pub label: String,
+    pub input: HookInput,
+}
+
+impl PromptExample {
+    /// SAFE examples should be allowed; UNSAFE and UNKNOWN ones queried
+    pub fn expected(&self) -> &'static str {
+        if self.label == "SAFE" { "ALLOW" } else { "QUERY" }
+    }
+}
+
+/// An example and what the model answered: ALLOW, QUERY, or the timeout/error
+#[derive(Debug)]
+pub struct ExampleOutcome {
+    pub example: PromptExample,
+    pub answer: Result<&'static str, String>,
+}
+
+impl ExampleOutcome {
+    pub fn agrees(&self) -> bool {
+        self.answer.as_ref().is_ok_and(|answer| *answer == self.example.expected())
+    }
+}
+
+/// Examples in the prompt, in order; lines that don't fit the format, or name a tool
+/// other than Bash, Read, Write, Edit or Glob, are skipped
+pub fn prompt_examples(system_prompt: &str) -> Vec<PromptExample> {
+    system_prompt
+        .lines()
+        .filter_map(|line| {
+            let (example, verdict) = line.trim().strip_prefix("- ")?.split_once(" → ")?;
+            let label = verdict.split_once(':').map_or(verdict, |(label, _)| label).trim();
+            if !matches!(label, "SAFE" | "UNSAFE" | "UNKNOWN") {
+                return None;
+            }
+            let (tool, argument) = example.split_once(' ')?;
+            let field = match tool {
+                "Bash" => "command",
+                "Read" | "Write" | "Edit" | "Glob" => "file_path",
+                _ => return None,
+            };
+            let argument = argument.trim().trim_matches('"');
+            let input = HookInput {
+                session_id: "prompt-check".to_string(),
+                transcript_path: String::new(),
+                cwd: "/home/user/project".to_string(),
+                hook_event_name: "PreToolUse".to_string(),
+                tool_name: tool.to_string(),
+                tool_input: serde_json::json!({ field: argument }),
+                extra: serde_json::Map::new(),
+                input_root: None,
+                path_style: Default::default(),
+            };
+            Some(PromptExample {
+                text: example.to_string(),
+                label: label.to_string(),
+                input,
+            })
+        })
+        .collect()
+}
+
+/// Ask the classifier about each example in turn
+pub async fn check_examples(
+    classifier: &impl SafetyClassifier,
+    examples: Vec<PromptExample>,
+) -> Vec<ExampleOutcome> {
+    let mut outcomes = Vec::new();
+    for example in examples {
+        let (result, _) = llm_safety::assess_with(classifier, &example.input, &[]).await;
+        let answer = match result {
+            AssessmentResult::Assessment(verdict) => Ok(match verdict.assessment {
+                SafetyAssessment::Allow(_) => "ALLOW",
+                SafetyAssessment::Query(_) => "QUERY",
+            }),
+            AssessmentResult::Timeout => Err("timed out".to_string()),
+            AssessmentResult::Error(e) => Err(e),
+        };
+        outcomes.push(ExampleOutcome { example, answer });
+    }
+    outcomes
+}
+
+/// One line per example and a closing count, returning how many disagreed
+pub fn write_report(out: &mut impl Write, outcomes: &[ExampleOutcome]) -> io::Result<usize> {
+    for outcome in outcomes {
+        let answer = match &outcome.answer {
+            Ok(answer) => answer.to_string(),
+            Err(e) => format!("error: {}", e),
+        };
+        writeln!(
+            out,
+            "{} {} → {} (expected {}, got {})",
+            if outcome.agrees() { "PASS" } else { "FAIL" },
+            outcome.example.text,
+            outcome.example.label,
+            outcome.example.expected(),
+            answer
+        )?;
+    }
+    let disagreed = outcomes.iter().filter(|outcome| !outcome.agrees()).count();
+    writeln!(out, "{}/{} examples agree with their labels", outcomes.len() - disagreed, outcomes.len())?;
+    Ok(disagreed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::LlmFallbackConfig;
+    use crate::llm_safety::HttpClassifier;
+    use crate::test_support::{chat_completion, spawn_mock_llm};
+
+    #[test]
+    fn test_default_prompt_examples_parse() {
+        let examples = prompt_examples(&LlmFallbackConfig::default().system_prompt);
+        let parsed: Vec<(&str, &str)> =
+            examples.iter().map(|e| (e.input.tool_name.as_str(), e.label.as_str())).collect();
+        assert_eq!(
+            parsed,
+            [("Read", "SAFE"), ("Bash", "UNSAFE"), ("Read", "UNSAFE"), ("Bash", "UNKNOWN"), ("Read", "UNSAFE")]
+        );
+        assert_eq!(examples[1].input.extract_field("command").as_deref(), Some("rm -rf /"));
+        assert_eq!(examples[0].expected(), "ALLOW");
+        assert_eq!(examples[3].expected(), "QUERY");
+    }
+
+    #[tokio::test]
+    async fn test_check_examples_against_mock_model() {
+        let answer = |classification: &str| {
+            let content = format!(r#"{{"classification": "{}", "reasoning": "mock"}}"#, classification);
+            (200, chat_completion(&content))
+        };
+        // The fourth answer is wrong: an UNKNOWN example should be queried
+        let (endpoint, requests) = spawn_mock_llm(vec![
+            answer("ALLOW"),
+            answer("QUERY"),
+            answer("QUERY"),
+            answer("ALLOW"),
+            answer("QUERY"),
+        ]);
+        let config = LlmFallbackConfig {
+            enabled: true,
+            endpoint: Some(endpoint),
+            model: Some("test-model".to_string()),
+            max_retries: 0,
+            ..LlmFallbackConfig::default()
+        };
+
+        let examples = prompt_examples(&config.system_prompt);
+        let outcomes = check_examples(&HttpClassifier::new(&config), examples).await;
+        assert_eq!(requests.lock().unwrap().len(), 5);
+
+        let mut report = Vec::new();
+        assert_eq!(write_report(&mut report, &outcomes).unwrap(), 1);
+        let report = String::from_utf8(report).unwrap();
+        assert!(
+            report.contains(r#"FAIL Bash "curl http://api.example.com" → UNKNOWN (expected QUERY, got ALLOW)"#),
+            "{}",
+            report
+        );
+        assert!(report.contains("PASS Read /etc/passwd → UNSAFE (expected QUERY, got QUERY)"), "{}", report);
+        assert!(report.ends_with("4/5 examples agree with their labels\n"), "{}", report);
+    }
+}
@@ -0,0 +1,117 @@
+#![forbid(unsafe_code)]
+#![warn(clippy::all)]
+
+use crate::config::SessionBudgetConfig;
+use crate::hook_io::session_file_stem;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use nix::fcntl::{Flock, FlockArg};
+use serde::{Deserialize, Serialize};
+use std::fs::OpenOptions;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+// ========== SESSION OPERATION BUDGET ==========
+// Purpose: Circuit breaker - deny everything once a session has made too many tool calls
+// Location: /tmp/claude-hook-budget/<session_id>.json (or configured directory)
+
+/// Operations counted for one session since `started_at`
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Counter {
+    count: u64,
+    started_at: i64,
+}
+
+fn counter_file(dir: &Path, session_id: &str) -> PathBuf {
+    dir.join(format!("{}.json", session_file_stem(session_id)))
+}
+
+/// Count one operation for `session_id` and return the session's new total
+/// The counter starts over once `reset_after_secs` have passed since its first operation;
+/// an unreadable counter file also starts over
+pub fn count_operation(config: &SessionBudgetConfig, session_id: &str, now: DateTime<Utc>) -> Result<u64> {
+    count_in(&config.dir, session_id, config.reset_after_secs, now)
+}
+
+/// `count_operation` against counter files under `dir`, for other per-session counts
+/// (`logging.verbose_first_n` keeps its own)
+pub fn count_in(
+    dir: &Path,
+    session_id: &str,
+    reset_after_secs: Option<u64>,
+    now: DateTime<Utc>,
+) -> Result<u64> {
+    std::fs::create_dir_all(dir)
+        .with_context(|| format!("Failed to create counter directory: {}", dir.display()))?;
+    let path = counter_file(dir, session_id);
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(&path)
+        .with_context(|| format!("Failed to open counter file: {}", path.display()))?;
+
+    // Held across the read and the write, so concurrent hook runs can't lose a count
+    let mut flock = Flock::lock(file, FlockArg::LockExclusive).map_err(|(_, e)| e)?;
+    let mut contents = String::new();
+    flock.read_to_string(&mut contents)?;
+    let mut counter: Counter = serde_json::from_str(&contents).unwrap_or_default();
+
+    let expired = reset_after_secs.is_some_and(|ttl| now.timestamp() - counter.started_at >= ttl as i64);
+    if counter.count == 0 || expired {
+        counter = Counter {
+            count: 0,
+            started_at: now.timestamp(),
+        };
+    }
+    counter.count += 1;
+
+    flock.set_len(0)?;
+    flock.seek(SeekFrom::Start(0))?;
+    flock.write_all(serde_json::to_string(&counter)?.as_bytes())?;
+    Ok(counter.count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn budget_config(reset_after_secs: Option<u64>) -> SessionBudgetConfig {
+        SessionBudgetConfig {
+            max_operations_per_session: Some(2),
+            dir: std::env::temp_dir().join(format!("hook-budget-test-{}", rand::random::<u64>())),
+            reset_after_secs,
+        }
+    }
+
+    #[test]
+    fn test_counts_per_session() {
+        let config = budget_config(None);
+        let now = Utc::now();
+        assert_eq!(count_operation(&config, "session-1", now).unwrap(), 1);
+        assert_eq!(count_operation(&config, "session-1", now).unwrap(), 2);
+        assert_eq!(count_operation(&config, "session-2", now).unwrap(), 1);
+        assert_eq!(count_operation(&config, "session-1", now).unwrap(), 3);
+    }
+
+    #[test]
+    fn test_counter_resets_after_ttl() {
+        let config = budget_config(Some(60));
+        let start = Utc::now();
+        assert_eq!(count_operation(&config, "session-1", start).unwrap(), 1);
+        let later = start + chrono::Duration::seconds(59);
+        assert_eq!(count_operation(&config, "session-1", later).unwrap(), 2);
+        // The window runs from the first operation, not the latest one
+        let expired = start + chrono::Duration::seconds(60);
+        assert_eq!(count_operation(&config, "session-1", expired).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_corrupt_counter_starts_over() {
+        let config = budget_config(None);
+        std::fs::create_dir_all(&config.dir).unwrap();
+        std::fs::write(counter_file(&config.dir, "session-1"), "not json").unwrap();
+        assert_eq!(count_operation(&config, "session-1", Utc::now()).unwrap(), 1);
+    }
+}
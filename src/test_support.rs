@@ -0,0 +1,53 @@
+#![forbid(unsafe_code)]
+#![warn(clippy::all)]
+
+//! Helpers shared by unit tests across modules
+
+/// Minimal HTTP server that answers each connection with the next canned
+/// (status, body) pair and records the request bodies it received
+pub fn spawn_mock_llm(
+    responses: Vec<(u16, String)>,
+) -> (String, std::sync::Arc<std::sync::Mutex<Vec<String>>>) {
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let endpoint = format!("http://{}", listener.local_addr().unwrap());
+    let requests = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    let recorded = requests.clone();
+
+    std::thread::spawn(move || {
+        for (status, body) in responses {
+            let Ok((stream, _)) = listener.accept() else { return };
+            let mut reader = BufReader::new(stream);
+            let mut content_length = 0;
+            loop {
+                let mut line = String::new();
+                if reader.read_line(&mut line).unwrap_or(0) == 0 || line == "\r\n" {
+                    break;
+                }
+                if let Some(len) = line.to_lowercase().strip_prefix("content-length:") {
+                    content_length = len.trim().parse().unwrap_or(0);
+                }
+            }
+            let mut request_body = vec![0; content_length];
+            reader.read_exact(&mut request_body).unwrap();
+            recorded.lock().unwrap().push(String::from_utf8_lossy(&request_body).to_string());
+
+            let mut stream = reader.into_inner();
+            let reply = format!(
+                "HTTP/1.1 {} Mock\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                status,
+                body.len(),
+                body
+            );
+            stream.write_all(reply.as_bytes()).unwrap();
+        }
+    });
+
+    (endpoint, requests)
+}
+
+pub fn chat_completion(content: &str) -> String {
+    serde_json::json!({"choices": [{"message": {"content": content}}]}).to_string()
+}